@@ -0,0 +1,107 @@
+;; Contract used by the `CallDepth` sample, built with `wat2wasm calls.wat`.
+;;
+;; Both methods take as arguments a sequence of account ids, each prefixed by its length as a
+;; single byte. `chain` calls `chain` on the first account with the rest of the sequence, so
+;; that the promise chain is as deep as the sequence is long. `fan_out` calls `chain` on every
+;; account of the sequence at once, with no arguments.
+;;
+;; Memory layout: method name at 0, amount of the calls (zero) at 16, arguments at 64.
+(module
+  (import "env" "input" (func $input (param i64)))
+  (import "env" "register_len" (func $register_len (param i64) (result i64)))
+  (import "env" "read_register" (func $read_register (param i64 i64)))
+  (import "env" "promise_batch_create" (func $promise_batch_create (param i64 i64) (result i64)))
+  (import "env" "promise_batch_action_function_call_weight"
+    (func $function_call_weight (param i64 i64 i64 i64 i64 i64 i64 i64)))
+  (import "env" "promise_return" (func $promise_return (param i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "chain")
+
+  (func (export "chain")
+    (local $len i64) (local $n i64) (local $idx i64)
+    i64.const 0
+    call $input
+    i64.const 0
+    call $register_len
+    local.tee $len
+    i64.eqz
+    if
+      return
+    end
+    i64.const 0
+    i64.const 64
+    call $read_register
+    i32.const 64
+    i64.load8_u
+    local.set $n
+    ;; promise_batch_create(account_len, account_ptr)
+    local.get $n
+    i64.const 65
+    call $promise_batch_create
+    local.tee $idx
+    ;; method name
+    i64.const 5
+    i64.const 0
+    ;; remaining accounts
+    local.get $len
+    local.get $n
+    i64.sub
+    i64.const 1
+    i64.sub
+    local.get $n
+    i64.const 65
+    i64.add
+    ;; amount, gas and gas weight: all the unused gas goes to the call
+    i64.const 16
+    i64.const 0
+    i64.const 1
+    call $function_call_weight
+    local.get $idx
+    call $promise_return)
+
+  (func (export "fan_out")
+    (local $len i64) (local $pos i64) (local $n i64)
+    i64.const 0
+    call $input
+    i64.const 0
+    call $register_len
+    local.set $len
+    i64.const 0
+    i64.const 64
+    call $read_register
+    block
+      loop
+        local.get $pos
+        local.get $len
+        i64.ge_u
+        br_if 1
+        local.get $pos
+        i64.const 64
+        i64.add
+        i32.wrap_i64
+        i64.load8_u
+        local.set $n
+        ;; promise_batch_create(account_len, account_ptr)
+        local.get $n
+        local.get $pos
+        i64.const 65
+        i64.add
+        call $promise_batch_create
+        ;; method name, no arguments, amount, gas and gas weight
+        i64.const 5
+        i64.const 0
+        i64.const 0
+        i64.const 0
+        i64.const 16
+        i64.const 0
+        i64.const 1
+        call $function_call_weight
+        local.get $pos
+        local.get $n
+        i64.add
+        i64.const 1
+        i64.add
+        local.set $pos
+        br 0
+      end
+    end))
@@ -0,0 +1,3 @@
+;; Contract deployed by the `ContractDeploy` sample, built with `wat2wasm ping.wat`.
+(module
+  (func (export "ping")))
@@ -2,15 +2,20 @@ use clap::Parser;
 use futures::try_join;
 use tokio::sync::oneshot;
 use tracing::info;
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use transaction_bench::config::{Mode, Opts};
-use transaction_bench::{Engine, MetricServer};
+use transaction_bench::telemetry::setup_tracing;
+use transaction_bench::{Engine, MetricPusher, MetricServer};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    setup_tracing();
     dotenv::dotenv().ok();
     let opts = Opts::parse();
+    let _tracing_guard = setup_tracing(
+        opts.otlp_endpoint
+            .as_ref()
+            .map(|endpoint| endpoint.0.as_str()),
+        &opts.location,
+    )?;
     let engine = Engine::with_default_transactions();
 
     match opts.mode {
@@ -24,25 +29,37 @@ async fn run(opts: Opts, engine: Engine) -> anyhow::Result<()> {
     let (shutdown_notice, shutdown_signal) = oneshot::channel::<()>();
     let metric_server = MetricServer::new(opts.metric_server_address);
     let metric_server_fut = metric_server.run(shutdown_notice);
+    let pusher = match (opts.push_protocol, opts.push_address.clone()) {
+        (Some(protocol), Some(address)) => Some(MetricPusher::new(
+            protocol,
+            address.0,
+            opts.push_interval,
+            opts.location.clone(),
+            metric_server.registry(),
+        )),
+        _ => None,
+    };
+    let push_fut = async {
+        match &pusher {
+            Some(pusher) => pusher.run().await,
+            None => std::future::pending().await,
+        }
+    };
     let engine_fut = engine.run(opts, metric_server.metrics.clone(), shutdown_signal);
-    try_join!(metric_server_fut, engine_fut).map(|_| ())
+    tokio::select! {
+        res = async { try_join!(metric_server_fut, engine_fut) } => res.map(|_| ()),
+        res = push_fut => res,
+    }
 }
 
 async fn list(engine: Engine) -> anyhow::Result<()> {
     info!("list of supported transactions:");
     for tx in engine.transactions().values() {
-        info!("  - {}", tx.kind());
+        if tx.enabled_by_default() {
+            info!("  - {}", tx.kind());
+        } else {
+            info!("  - {} (only when selected in TRANSACTION_KIND)", tx.kind());
+        }
     }
     Ok(())
 }
-
-fn setup_tracing() {
-    let fmt_layer = fmt::layer().with_target(false);
-    let filter_layer = EnvFilter::try_from_default_env()
-        .or_else(|_| EnvFilter::try_new("info"))
-        .expect("failed to create env filter for tracing");
-    tracing_subscriber::registry()
-        .with(filter_layer)
-        .with(fmt_layer)
-        .init();
-}
@@ -0,0 +1,151 @@
+//! Module to load access keys from NEAR credentials files and mounted secrets.
+
+use anyhow::Context;
+use near_crypto::SecretKey;
+use near_primitives::types::AccountId;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::config::{Opts, Secret};
+
+/// Fills the signer, receiver and relayer keys that are not set directly, from their key file if
+/// set, otherwise from the credentials of their account for `network` in the credentials
+/// directory. Only the signer key is required, unless a remote signer holds it.
+pub fn load_keys(opts: &mut Opts, network: &str) -> anyhow::Result<()> {
+    let credentials_dir = opts
+        .near_credentials_dir
+        .clone()
+        .or_else(default_credentials_dir);
+    let dir = credentials_dir.as_deref();
+
+    if opts.signer_key.is_none() && opts.remote_signer_url.is_none() {
+        let key = load_account_key(&opts.signer_id, opts.signer_key_file.as_deref(), dir, network)?
+            .with_context(|| {
+                format!(
+                    "no key for signer {}, set SIGNER_KEY, SIGNER_KEY_FILE or add it to the NEAR credentials",
+                    opts.signer_id
+                )
+            })?;
+        opts.signer_key = Some(Secret(key));
+    }
+    if opts.receiver_key.is_none() {
+        opts.receiver_key = load_account_key(
+            &opts.receiver_id,
+            opts.receiver_key_file.as_deref(),
+            dir,
+            network,
+        )?
+        .map(Secret);
+    }
+    if let (None, Some(relayer_id)) = (&opts.relayer_key, &opts.relayer_id) {
+        opts.relayer_key =
+            load_account_key(relayer_id, opts.relayer_key_file.as_deref(), dir, network)?
+                .map(Secret);
+    }
+    Ok(())
+}
+
+fn load_account_key(
+    account_id: &AccountId,
+    key_file: Option<&Path>,
+    credentials_dir: Option<&Path>,
+    network: &str,
+) -> anyhow::Result<Option<SecretKey>> {
+    let path = match (key_file, credentials_dir) {
+        (Some(path), _) => path.to_path_buf(),
+        (None, Some(dir)) => {
+            let path = credentials_path(dir, network, account_id);
+            if !path.exists() {
+                return Ok(None);
+            }
+            path
+        }
+        (None, None) => return Ok(None),
+    };
+    info!("loading key of {} from {}", account_id, path.display());
+    let (file_account_id, key) =
+        load_key(&path).with_context(|| format!("failed to load key from {}", path.display()))?;
+    if file_account_id.is_some_and(|file_account_id| file_account_id != *account_id) {
+        warn!(
+            "key file {} does not belong to {}",
+            path.display(),
+            account_id
+        );
+    }
+    Ok(Some(key))
+}
+
+/// Reads a key from a NEAR credentials JSON file or a file holding only the key, such as a
+/// mounted secret. Returns the account id of the credentials, if any.
+fn load_key(path: &Path) -> anyhow::Result<(Option<AccountId>, SecretKey)> {
+    let content = std::fs::read_to_string(path)?;
+    let Ok(credentials) = serde_json::from_str::<serde_json::Value>(&content) else {
+        return Ok((None, content.trim().parse()?));
+    };
+    // Credentials written by NEAR CLI, older versions name the key `secret_key`.
+    let key = credentials["private_key"]
+        .as_str()
+        .or(credentials["secret_key"].as_str())
+        .context("credentials have no private_key")?;
+    let account_id = credentials["account_id"]
+        .as_str()
+        .map(str::parse)
+        .transpose()?;
+    Ok((account_id, key.parse()?))
+}
+
+fn credentials_path(dir: &Path, network: &str, account_id: &AccountId) -> PathBuf {
+    dir.join(network).join(format!("{}.json", account_id))
+}
+
+fn default_credentials_dir() -> Option<PathBuf> {
+    homedir::get_my_home()
+        .ok()
+        .flatten()
+        .map(|home| home.join(".near-credentials"))
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::KeyType;
+
+    use crate::transaction::test_utils::create_test_run_opts;
+
+    use super::*;
+
+    #[test]
+    fn test_load_keys() {
+        let dir = std::env::temp_dir().join(format!("tx-bench-credentials-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("testnet")).unwrap();
+        let signer_key = SecretKey::from_random(KeyType::ED25519);
+        std::fs::write(
+            dir.join("testnet").join("cat.near.json"),
+            serde_json::json!({
+                "account_id": "cat.near",
+                "public_key": signer_key.public_key(),
+                "private_key": signer_key,
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let relayer_key = SecretKey::from_random(KeyType::ED25519);
+        let relayer_key_file = dir.join("relayer-key");
+        std::fs::write(&relayer_key_file, format!("{}\n", relayer_key)).unwrap();
+
+        let mut opts = Opts {
+            signer_key: None,
+            relayer_id: Some("owl.near".parse().unwrap()),
+            relayer_key_file: Some(relayer_key_file),
+            near_credentials_dir: Some(dir.clone()),
+            ..create_test_run_opts()
+        };
+        load_keys(&mut opts, "testnet").unwrap();
+        assert_eq!(opts.signer_key, Some(Secret(signer_key)));
+        assert_eq!(opts.receiver_key, None);
+        assert_eq!(opts.relayer_key, Some(Secret(relayer_key)));
+
+        opts.signer_key = None;
+        assert!(load_keys(&mut opts, "mainnet").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+//! Module to estimate the gas required by function calls from recent transactions.
+
+use near_primitives::types::Gas;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::metrics::Labels;
+
+/// Number of recent transactions the estimate is based on.
+const OBSERVATIONS: usize = 20;
+/// Maximum gas attached to a transaction.
+pub const MAX_GAS: Gas = 300_000_000_000_000;
+
+/// Keeps the gas burnt by the last transactions of every label that succeeded or ran out of gas.
+#[derive(Default)]
+pub struct GasEstimator {
+    observations: Mutex<HashMap<Labels, VecDeque<Gas>>>,
+}
+
+impl GasEstimator {
+    pub fn record(&self, labels: &Labels, gas_burnt: Gas) {
+        let mut observations = self
+            .observations
+            .lock()
+            .expect("gas estimator lock poisoned");
+        let recent = observations.entry(labels.clone()).or_default();
+        if recent.len() == OBSERVATIONS {
+            recent.pop_front();
+        }
+        recent.push_back(gas_burnt);
+    }
+
+    /// Returns the largest recent gas burnt increased by `margin`, or `None` without
+    /// observations. The estimate covers the whole transaction, not each of its function calls.
+    pub fn estimate(&self, labels: &Labels, margin: f64) -> Option<Gas> {
+        let observations = self
+            .observations
+            .lock()
+            .expect("gas estimator lock poisoned");
+        let max = observations.get(labels)?.iter().max()?;
+        Some(((*max as f64 * (1.0 + margin)) as Gas).min(MAX_GAS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let estimator = GasEstimator::default();
+        let labels = Labels::new(
+            "swap".to_string(),
+            String::new(),
+            "mainnet".to_string(),
+            "eu".to_string(),
+        );
+        assert_eq!(estimator.estimate(&labels, 0.5), None);
+
+        estimator.record(&labels, 40_000_000_000_000);
+        for _ in 0..OBSERVATIONS - 1 {
+            estimator.record(&labels, 10_000_000_000_000);
+        }
+        assert_eq!(estimator.estimate(&labels, 0.5), Some(60_000_000_000_000));
+        assert_eq!(estimator.estimate(&labels, 10.0), Some(MAX_GAS));
+
+        // The largest observation falls out of the window.
+        estimator.record(&labels, 10_000_000_000_000);
+        assert_eq!(estimator.estimate(&labels, 0.5), Some(15_000_000_000_000));
+    }
+}
@@ -0,0 +1,29 @@
+//! Module to encode unsigned LEB128 integers, used by protobuf varints and WebAssembly sections.
+
+/// Encodes an unsigned LEB128 integer.
+pub(crate) fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_varint() {
+        for (value, expected) in [
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (300, vec![0xac, 0x02]),
+        ] {
+            let mut buf = vec![];
+            encode_varint(&mut buf, value);
+            assert_eq!(buf, expected);
+        }
+    }
+}
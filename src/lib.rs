@@ -1,7 +1,17 @@
 pub mod config;
+pub mod credentials;
+pub mod gas;
+mod leb128;
 
 pub mod metrics;
 pub use metrics::MetricServer;
 
+pub mod push;
+pub use push::MetricPusher;
+
+pub mod slo;
+
+pub mod telemetry;
+
 pub mod transaction;
 pub use transaction::{engine::Engine, TransactionKind, TransactionSample};
@@ -7,9 +7,12 @@ use axum::{routing::get, Router};
 use derive_more::Constructor;
 use prometheus_client::encoding::EncodeLabelSet;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::Unit;
 use prometheus_client::{encoding::text::encode, metrics::counter::Counter, registry::Registry};
+use std::fmt;
+use std::sync::atomic::AtomicU64;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
 use tokio::signal;
@@ -17,19 +20,60 @@ use tokio::sync::oneshot::Sender;
 use tower_http::timeout::TimeoutLayer;
 use tracing::info;
 
+use crate::gas::GasEstimator;
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet, Constructor)]
 pub struct Labels {
     kind: String,
+    /// Sample specific parameters of the transaction, e.g. the MPC domain. Empty for most kinds.
+    variant: String,
     network: String,
     location: String,
 }
 
+impl fmt::Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if !self.variant.is_empty() {
+            write!(f, "[{}]", self.variant)?;
+        }
+        write!(f, "/{}/{}", self.network, self.location)
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet, Constructor)]
+pub struct SloLabels {
+    objective: String,
+    // Flattened fields must come last, the derive macro consumes the encoder on them.
+    #[prometheus(flatten)]
+    labels: Labels,
+}
+
 pub struct Metrics {
     pub attempted_transactions: Family<Labels, Counter>,
     pub successful_transactions: Family<Labels, Counter>,
     pub failed_transactions: Family<Labels, Counter>,
     pub timeouts: Family<Labels, Counter>,
+    pub retries: Family<Labels, Counter>,
+    pub invalid_results: Family<Labels, Counter>,
+    pub batch_failed_transactions: Family<Labels, Counter>,
     pub transaction_latency: Family<Labels, Histogram>,
+    pub transaction_signing_latency: Family<Labels, Histogram>,
+    pub mpc_queue_latency: Family<Labels, Histogram>,
+    pub mpc_signing_latency: Family<Labels, Histogram>,
+    pub mpc_blocks_elapsed: Family<Labels, Histogram>,
+    pub receipts: Family<Labels, Histogram>,
+    pub gas_burnt: Family<Labels, Counter>,
+    pub gas_burnt_per_transaction: Family<Labels, Histogram>,
+    pub tokens_burnt: Family<Labels, Counter<f64, AtomicU64>>,
+    pub estimated_daily_spend: Family<Labels, Gauge<f64, AtomicU64>>,
+    pub last_attempt_timestamp: Family<Labels, Gauge<f64, AtomicU64>>,
+    pub last_success_timestamp: Family<Labels, Gauge<f64, AtomicU64>>,
+    pub last_latency: Family<Labels, Gauge<f64, AtomicU64>>,
+    pub in_flight_transactions: Family<Labels, Gauge>,
+    pub slo_burn: Family<SloLabels, Gauge<f64, AtomicU64>>,
+    /// Not exported, recent gas burnt used to estimate the gas of function calls.
+    pub gas_estimator: GasEstimator,
 }
 
 pub struct MetricServer {
@@ -48,6 +92,11 @@ impl MetricServer {
         }
     }
 
+    /// Returns the registry holding all metrics, e.g. to push them elsewhere.
+    pub fn registry(&self) -> Arc<Registry> {
+        self.registry.clone()
+    }
+
     pub async fn run(&self, shutdown_notice: Sender<()>) -> anyhow::Result<()> {
         info!("starting metrics server on {}", self.address);
 
@@ -133,6 +182,24 @@ pub(crate) fn create_registry_and_metrics() -> (Arc<Registry>, Arc<Metrics>) {
     );
     let timeouts = Family::<Labels, Counter>::default();
     registry.register("timeouts", "Number of timeouts", timeouts.clone());
+    let retries = Family::<Labels, Counter>::default();
+    registry.register(
+        "retries",
+        "Number of RPC requests retried after a transient failure",
+        retries.clone(),
+    );
+    let invalid_results = Family::<Labels, Counter>::default();
+    registry.register(
+        "invalid_result",
+        "Number of failed transactions whose result did not pass verification",
+        invalid_results.clone(),
+    );
+    let batch_failed_transactions = Family::<Labels, Counter>::default();
+    registry.register(
+        "batch_failed_tx",
+        "Number of failed transactions within batches of transactions",
+        batch_failed_transactions.clone(),
+    );
     let transaction_latency = Family::<Labels, Histogram>::new_with_constructor(|| {
         Histogram::new(exponential_buckets(2.0, 2.0, 6))
     });
@@ -142,12 +209,134 @@ pub(crate) fn create_registry_and_metrics() -> (Arc<Registry>, Arc<Metrics>) {
         Unit::Seconds,
         transaction_latency.clone(),
     );
+    let transaction_signing_latency = Family::<Labels, Histogram>::new_with_constructor(|| {
+        Histogram::new(exponential_buckets(0.001, 2.0, 12))
+    });
+    registry.register_with_unit(
+        "tx_signing_latency",
+        "Time spent building and signing a transaction, not included in its latency",
+        Unit::Seconds,
+        transaction_signing_latency.clone(),
+    );
+    let mpc_queue_latency = Family::<Labels, Histogram>::new_with_constructor(|| {
+        Histogram::new(exponential_buckets(0.5, 2.0, 8))
+    });
+    registry.register_with_unit(
+        "mpc_queue_latency",
+        "Time between sending an MPC request and its receipt yielding in the MPC contract",
+        Unit::Seconds,
+        mpc_queue_latency.clone(),
+    );
+    let mpc_signing_latency = Family::<Labels, Histogram>::new_with_constructor(|| {
+        Histogram::new(exponential_buckets(1.0, 2.0, 8))
+    });
+    registry.register_with_unit(
+        "mpc_signing_latency",
+        "Time between an MPC request yielding and being resumed with the response",
+        Unit::Seconds,
+        mpc_signing_latency.clone(),
+    );
+    let mpc_blocks_elapsed = Family::<Labels, Histogram>::new_with_constructor(|| {
+        Histogram::new(exponential_buckets(1.0, 2.0, 8))
+    });
+    registry.register(
+        "mpc_blocks_elapsed",
+        "Number of blocks between an MPC request yielding and being resumed",
+        mpc_blocks_elapsed.clone(),
+    );
+    let receipts = Family::<Labels, Histogram>::new_with_constructor(|| {
+        Histogram::new(exponential_buckets(1.0, 2.0, 8))
+    });
+    registry.register(
+        "receipts",
+        "Number of receipts executed by successful transactions",
+        receipts.clone(),
+    );
+    let gas_burnt = Family::<Labels, Counter>::default();
+    registry.register(
+        "gas_burnt",
+        "Gas burnt by transactions and all their receipts",
+        gas_burnt.clone(),
+    );
+    let gas_burnt_per_transaction = Family::<Labels, Histogram>::new_with_constructor(|| {
+        Histogram::new(exponential_buckets(100_000_000_000.0, 2.0, 13))
+    });
+    registry.register(
+        "tx_gas_burnt",
+        "Gas burnt by a transaction and all its receipts",
+        gas_burnt_per_transaction.clone(),
+    );
+    let tokens_burnt = Family::<Labels, Counter<f64, AtomicU64>>::default();
+    registry.register_with_unit(
+        "tokens_burnt",
+        "NEAR burnt by transactions and all their receipts",
+        Unit::Other("near".to_string()),
+        tokens_burnt.clone(),
+    );
+    let estimated_daily_spend = Family::<Labels, Gauge<f64, AtomicU64>>::default();
+    registry.register_with_unit(
+        "estimated_daily_spend",
+        "NEAR burnt in a day at the cost of the last transaction and the configured interval",
+        Unit::Other("near".to_string()),
+        estimated_daily_spend.clone(),
+    );
+    let last_attempt_timestamp = Family::<Labels, Gauge<f64, AtomicU64>>::default();
+    registry.register_with_unit(
+        "last_attempt_timestamp",
+        "Unix time of the last attempted transaction",
+        Unit::Seconds,
+        last_attempt_timestamp.clone(),
+    );
+    let last_success_timestamp = Family::<Labels, Gauge<f64, AtomicU64>>::default();
+    registry.register_with_unit(
+        "last_success_timestamp",
+        "Unix time of the last successful transaction",
+        Unit::Seconds,
+        last_success_timestamp.clone(),
+    );
+    let last_latency = Family::<Labels, Gauge<f64, AtomicU64>>::default();
+    registry.register_with_unit(
+        "last_tx_latency",
+        "Latency of the last successful transaction",
+        Unit::Seconds,
+        last_latency.clone(),
+    );
+    let in_flight_transactions = Family::<Labels, Gauge>::default();
+    registry.register(
+        "in_flight_tx",
+        "Number of transactions currently being executed",
+        in_flight_transactions.clone(),
+    );
+    let slo_burn = Family::<SloLabels, Gauge<f64, AtomicU64>>::default();
+    registry.register(
+        "slo_burn",
+        "Burn rate of the service level objective, breached when above 1",
+        slo_burn.clone(),
+    );
     let metrics = Metrics {
         attempted_transactions,
         successful_transactions,
         failed_transactions,
         timeouts,
+        retries,
+        invalid_results,
+        batch_failed_transactions,
         transaction_latency,
+        transaction_signing_latency,
+        mpc_queue_latency,
+        mpc_signing_latency,
+        mpc_blocks_elapsed,
+        receipts,
+        gas_burnt,
+        gas_burnt_per_transaction,
+        tokens_burnt,
+        estimated_daily_spend,
+        last_attempt_timestamp,
+        last_success_timestamp,
+        last_latency,
+        in_flight_transactions,
+        slo_burn,
+        gas_estimator: GasEstimator::default(),
     };
     (Arc::new(registry), Arc::new(metrics))
 }
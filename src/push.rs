@@ -0,0 +1,418 @@
+//! Module to push Prometheus metrics to receivers that cannot scrape the metric server.
+
+use prometheus_client::{encoding::text::encode, registry::Registry};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use crate::config::PushProtocol;
+use crate::leb128::encode_varint;
+
+const JOB_NAME: &str = "tx_bench";
+
+/// A single sample parsed from the text exposition format.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Sample {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+pub struct MetricPusher {
+    protocol: PushProtocol,
+    address: String,
+    period: Duration,
+    location: String,
+    registry: Arc<Registry>,
+    http_client: reqwest::Client,
+}
+
+impl MetricPusher {
+    pub fn new(
+        protocol: PushProtocol,
+        address: String,
+        period: Duration,
+        location: String,
+        registry: Arc<Registry>,
+    ) -> Self {
+        Self {
+            protocol,
+            address,
+            period,
+            location,
+            registry,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// Pushes metrics every `period` until the program is stopped. Failed pushes are logged and
+    /// retried at the next tick.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        info!(
+            "pushing metrics to {} ({:?}) every {:?}",
+            self.address, self.protocol, self.period
+        );
+        let mut interval = interval(self.period);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.push_once().await {
+                warn!("failed to push metrics to {}: {}", self.address, err);
+            }
+        }
+    }
+
+    /// Pushes the current value of all metrics once.
+    pub async fn push_once(&self) -> anyhow::Result<()> {
+        let mut text = String::new();
+        encode(&mut text, &self.registry)?;
+        match self.protocol {
+            PushProtocol::Pushgateway => self.push_to_gateway(text).await,
+            PushProtocol::RemoteWrite => self.push_remote_write(&parse_text_format(&text)).await,
+            PushProtocol::Statsd | PushProtocol::Dogstatsd => {
+                self.push_statsd(&parse_text_format(&text)).await
+            }
+        }
+    }
+
+    async fn push_to_gateway(&self, text: String) -> anyhow::Result<()> {
+        // Path segments are percent-encoded, so any location is a valid grouping label.
+        let mut url = reqwest::Url::parse(&self.address)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid Pushgateway URL {}", self.address))?
+            .pop_if_empty()
+            .extend(["metrics", "job", JOB_NAME, "instance", &self.location]);
+        self.http_client
+            .put(url)
+            // The encoder writes OpenMetrics, whose counter names differ from the text format.
+            .header(
+                CONTENT_TYPE,
+                "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            )
+            .body(text)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn push_remote_write(&self, samples: &[Sample]) -> anyhow::Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let body =
+            snap::raw::Encoder::new().compress_vec(&encode_write_request(samples, timestamp))?;
+        self.http_client
+            .post(&self.address)
+            .header(CONTENT_TYPE, "application/x-protobuf")
+            .header(CONTENT_ENCODING, "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn push_statsd(&self, samples: &[Sample]) -> anyhow::Result<()> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(&self.address).await?;
+        for sample in samples {
+            let line = match self.protocol {
+                PushProtocol::Dogstatsd => format_dogstatsd(sample),
+                _ => format_statsd(sample),
+            };
+            debug!("statsd: {}", line);
+            socket.send(line.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the output of the text exposition encoder into samples, skipping comments.
+pub(crate) fn parse_text_format(text: &str) -> Vec<Sample> {
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_sample)
+        .collect()
+}
+
+fn parse_sample(line: &str) -> Option<Sample> {
+    let (series, value) = line.rsplit_once(' ')?;
+    let value = value.parse().ok()?;
+    let Some((name, rest)) = series.split_once('{') else {
+        return Some(Sample {
+            name: series.to_string(),
+            labels: vec![],
+            value,
+        });
+    };
+    let mut labels = vec![];
+    let mut chars = rest.strip_suffix('}')?.chars().peekable();
+    while chars.peek().is_some() {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if chars.next() != Some('"') {
+            return None;
+        }
+        let mut val = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next()? {
+                    'n' => val.push('\n'),
+                    escaped => val.push(escaped),
+                },
+                '"' => break,
+                c => val.push(c),
+            }
+        }
+        labels.push((key, val));
+        if chars.peek() == Some(&',') {
+            chars.next();
+        }
+    }
+    Some(Sample {
+        name: name.to_string(),
+        labels,
+        value,
+    })
+}
+
+fn format_statsd(sample: &Sample) -> String {
+    let mut name = sample.name.clone();
+    // Empty label values are equivalent to missing labels in Prometheus.
+    for (_, value) in sample.labels.iter().filter(|(_, value)| !value.is_empty()) {
+        name.push('.');
+        name.extend(value.chars().map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        }));
+    }
+    format!("{}:{}|g", name, sample.value)
+}
+
+fn format_dogstatsd(sample: &Sample) -> String {
+    let tags: Vec<String> = sample
+        .labels
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}:{}", key, value))
+        .collect();
+    if tags.is_empty() {
+        return format!("{}:{}|g", sample.name, sample.value);
+    }
+    format!("{}:{}|g|#{}", sample.name, sample.value, tags.join(","))
+}
+
+/// Encodes samples as a remote write `WriteRequest` protobuf message.
+fn encode_write_request(samples: &[Sample], timestamp_ms: i64) -> Vec<u8> {
+    let mut request = vec![];
+    for sample in samples {
+        let mut series = vec![];
+        let name_label = ("__name__".to_string(), sample.name.clone());
+        // Remote write requires labels to be sorted by name.
+        let mut labels: Vec<&(String, String)> = std::iter::once(&name_label)
+            .chain(&sample.labels)
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        labels.sort();
+        for (name, value) in labels {
+            let mut label = vec![];
+            encode_bytes_field(&mut label, 1, name.as_bytes());
+            encode_bytes_field(&mut label, 2, value.as_bytes());
+            encode_bytes_field(&mut series, 1, &label);
+        }
+        let mut point = vec![];
+        encode_key(&mut point, 1, 1);
+        point.extend_from_slice(&sample.value.to_le_bytes());
+        encode_key(&mut point, 2, 0);
+        encode_varint(&mut point, timestamp_ms as u64);
+        encode_bytes_field(&mut series, 2, &point);
+        encode_bytes_field(&mut request, 1, &series);
+    }
+    request
+}
+
+fn encode_key(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+    encode_varint(buf, (field << 3) | wire_type);
+}
+
+fn encode_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    encode_key(buf, field, 2);
+    encode_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Bytes,
+        extract::Path,
+        http::HeaderMap,
+        routing::{post, put},
+        Router,
+    };
+    use prometheus_client::metrics::counter::Counter;
+    use prometheus_client::metrics::family::Family;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use crate::metrics::Labels;
+
+    use super::*;
+
+    fn create_test_registry() -> Arc<Registry> {
+        let mut registry = <Registry>::with_prefix("tx_bench");
+        let counter = Family::<Labels, Counter>::default();
+        registry.register(
+            "attempted_tx",
+            "Number of attempted transactions",
+            counter.clone(),
+        );
+        counter
+            .get_or_create(&Labels::new(
+                "swap".to_string(),
+                String::new(),
+                "mainnet".to_string(),
+                "eu".to_string(),
+            ))
+            .inc_by(3);
+        Arc::new(registry)
+    }
+
+    #[test]
+    fn test_parse_text_format() {
+        let text = "# HELP a_total help.\n# TYPE a_total counter\n\
+                    a_total{kind=\"swap\",le=\"+Inf\",q=\"a\\\"b\"} 3\nb 1.5\n# EOF\n";
+        assert_eq!(
+            parse_text_format(text),
+            vec![
+                Sample {
+                    name: "a_total".to_string(),
+                    labels: vec![
+                        ("kind".to_string(), "swap".to_string()),
+                        ("le".to_string(), "+Inf".to_string()),
+                        ("q".to_string(), "a\"b".to_string()),
+                    ],
+                    value: 3.0,
+                },
+                Sample {
+                    name: "b".to_string(),
+                    labels: vec![],
+                    value: 1.5,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_write_request() {
+        let sample = Sample {
+            name: "a".to_string(),
+            labels: vec![],
+            value: 1.0,
+        };
+        let mut expected = vec![0x0a, 0x1c, 0x0a, 0x0d, 0x0a, 0x08];
+        expected.extend_from_slice(b"__name__");
+        expected.extend_from_slice(&[0x12, 0x01, b'a', 0x12, 0x0b, 0x09]);
+        expected.extend_from_slice(&1.0f64.to_le_bytes());
+        expected.extend_from_slice(&[0x10, 0x02]);
+        assert_eq!(encode_write_request(&[sample], 2), expected);
+    }
+
+    #[tokio::test]
+    async fn test_push_statsd() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = receiver.local_addr().unwrap().to_string();
+        for (protocol, expected) in [
+            (
+                PushProtocol::Statsd,
+                "tx_bench_attempted_tx_total.swap.mainnet.eu:3|g",
+            ),
+            (
+                PushProtocol::Dogstatsd,
+                "tx_bench_attempted_tx_total:3|g|#kind:swap,network:mainnet,location:eu",
+            ),
+        ] {
+            let pusher = MetricPusher::new(
+                protocol,
+                address.clone(),
+                Duration::from_secs(1),
+                "eu".to_string(),
+                create_test_registry(),
+            );
+            pusher.push_once().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let len = receiver.recv(&mut buf).await.unwrap();
+            assert_eq!(std::str::from_utf8(&buf[..len]).unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_to_gateway() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/metrics/job/:job/instance/:instance",
+            put(
+                move |Path(path): Path<(String, String)>, body: Bytes| async move {
+                    sender.send((path, body)).unwrap();
+                },
+            ),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let pusher = MetricPusher::new(
+            PushProtocol::Pushgateway,
+            format!("{}/", address),
+            Duration::from_secs(1),
+            "eu/west 1".to_string(),
+            create_test_registry(),
+        );
+        pusher.push_once().await.unwrap();
+
+        let (path, body) = receiver.recv().await.unwrap();
+        assert_eq!(path, (JOB_NAME.to_string(), "eu/west 1".to_string()));
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(
+            "tx_bench_attempted_tx_total{kind=\"swap\",variant=\"\",network=\"mainnet\",location=\"eu\"} 3"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_push_remote_write() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/api/v1/write",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                sender.send((headers, body)).unwrap();
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}/api/v1/write", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let pusher = MetricPusher::new(
+            PushProtocol::RemoteWrite,
+            address,
+            Duration::from_secs(1),
+            "eu".to_string(),
+            create_test_registry(),
+        );
+        pusher.push_once().await.unwrap();
+
+        let (headers, body) = receiver.recv().await.unwrap();
+        assert_eq!(headers[CONTENT_TYPE], "application/x-protobuf");
+        assert_eq!(headers[CONTENT_ENCODING], "snappy");
+        let request = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        let mut expected_label = vec![];
+        encode_bytes_field(&mut expected_label, 1, b"__name__");
+        encode_bytes_field(&mut expected_label, 2, b"tx_bench_attempted_tx_total");
+        assert!(request
+            .windows(expected_label.len())
+            .any(|window| window == expected_label));
+        assert!(request
+            .windows(3.0f64.to_le_bytes().len())
+            .any(|window| window == 3.0f64.to_le_bytes()));
+    }
+}
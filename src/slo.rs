@@ -0,0 +1,300 @@
+//! Module to evaluate service level objectives from recent benchmark results.
+
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use strum_macros::Display;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::metrics::{Labels, Metrics, SloLabels};
+use crate::TransactionKind;
+
+/// Objectives of a single transaction kind. Unset objectives are not evaluated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Objective {
+    /// Maximum latency at `latency_percentile` of successful transactions.
+    pub latency: Option<Duration>,
+    /// Percentile of the latency objective, between 0 and 1.
+    pub latency_percentile: f64,
+    /// Minimum ratio of successful transactions, between 0 and 1.
+    pub success_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ObjectiveKind {
+    Latency,
+    SuccessRate,
+}
+
+/// Format of the payload posted to the alert webhook.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// Slack incoming webhook (`{"text": ...}`).
+    Slack,
+    /// PagerDuty Events API v2.
+    Pagerduty,
+}
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub format: WebhookFormat,
+    /// Routing key, required by PagerDuty.
+    pub routing_key: Option<String>,
+}
+
+#[derive(Default)]
+struct ResultsWindow {
+    /// Completion time and latency of recent transactions, `None` for failures.
+    results: VecDeque<(Instant, Option<Duration>)>,
+    breached: HashMap<ObjectiveKind, bool>,
+}
+
+/// Keeps the results of the last `window` per transaction kind and reports objectives whose
+/// burn rate exceeds 1, both as `slo_burn` gauges and through an optional webhook. Objectives
+/// are evaluated only once the window holds `min_samples` results, so a single early failure
+/// does not breach them.
+pub struct SloMonitor {
+    objectives: HashMap<TransactionKind, Objective>,
+    window: Duration,
+    min_samples: usize,
+    webhook: Option<Webhook>,
+    http_client: reqwest::Client,
+    windows: Mutex<HashMap<Labels, ResultsWindow>>,
+}
+
+impl SloMonitor {
+    pub fn new(
+        objectives: HashMap<TransactionKind, Objective>,
+        window: Duration,
+        min_samples: usize,
+        webhook: Option<Webhook>,
+    ) -> Self {
+        Self {
+            objectives,
+            window,
+            min_samples,
+            webhook,
+            http_client: reqwest::Client::new(),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records the result of a transaction and re-evaluates the objectives of its kind.
+    pub fn record(
+        &self,
+        kind: &TransactionKind,
+        labels: &Labels,
+        metrics: &Metrics,
+        latency: Option<Duration>,
+    ) {
+        let Some(objective) = self.objectives.get(kind) else {
+            return;
+        };
+        let now = Instant::now();
+        let mut windows = self.windows.lock().expect("SLO window lock poisoned");
+        let window = windows.entry(labels.clone()).or_default();
+        window.results.push_back((now, latency));
+        while let Some((time, _)) = window.results.front() {
+            if now.duration_since(*time) <= self.window {
+                break;
+            }
+            window.results.pop_front();
+        }
+        if window.results.len() < self.min_samples {
+            return;
+        }
+
+        for (objective_kind, burn) in evaluate(objective, &window.results) {
+            metrics
+                .slo_burn
+                .get_or_create(&SloLabels::new(objective_kind.to_string(), labels.clone()))
+                .set(burn);
+            let breached = burn > 1.0;
+            let was_breached = window.breached.insert(objective_kind, breached);
+            if was_breached.unwrap_or(false) != breached {
+                self.notify(labels, objective_kind, burn, breached);
+            }
+        }
+    }
+
+    fn notify(&self, labels: &Labels, objective_kind: ObjectiveKind, burn: f64, breached: bool) {
+        let summary = format!(
+            "SLO {} for {} {}: burn rate {:.2}",
+            objective_kind,
+            labels,
+            if breached { "breached" } else { "recovered" },
+            burn
+        );
+        if breached {
+            warn!("{}", summary);
+        } else {
+            info!("{}", summary);
+        }
+        let Some(webhook) = self.webhook.clone() else {
+            return;
+        };
+        let payload = match webhook.format {
+            WebhookFormat::Slack => json!({ "text": summary }),
+            WebhookFormat::Pagerduty => json!({
+                "routing_key": webhook.routing_key,
+                "event_action": if breached { "trigger" } else { "resolve" },
+                "dedup_key": format!("tx_bench/{}/{}", labels, objective_kind),
+                "payload": {
+                    "summary": summary,
+                    "source": labels.to_string(),
+                    "severity": "error",
+                },
+            }),
+        };
+        let http_client = self.http_client.clone();
+        tokio::spawn(async move {
+            let res = http_client
+                .post(&webhook.url)
+                .json(&payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(err) = res {
+                warn!("failed to send SLO alert to webhook: {}", err);
+            }
+        });
+    }
+}
+
+/// Computes the burn rate of each objective: the observed value divided by the allowed one.
+fn evaluate(
+    objective: &Objective,
+    results: &VecDeque<(Instant, Option<Duration>)>,
+) -> Vec<(ObjectiveKind, f64)> {
+    let mut burns = vec![];
+    if let Some(target) = objective.success_rate {
+        let failures = results.iter().filter(|(_, res)| res.is_none()).count();
+        let error_rate = failures as f64 / results.len() as f64;
+        let error_budget = 1.0 - target;
+        let burn = if error_budget > 0.0 {
+            error_rate / error_budget
+        } else if failures > 0 {
+            f64::INFINITY
+        } else {
+            0.0
+        };
+        burns.push((ObjectiveKind::SuccessRate, burn));
+    }
+    if let Some(threshold) = objective.latency {
+        let mut latencies: Vec<Duration> = results.iter().filter_map(|(_, res)| *res).collect();
+        if !latencies.is_empty() {
+            latencies.sort();
+            let rank = (objective.latency_percentile * latencies.len() as f64).ceil() as usize;
+            let observed = latencies[rank.clamp(1, latencies.len()) - 1];
+            burns.push((
+                ObjectiveKind::Latency,
+                observed.as_secs_f64() / threshold.as_secs_f64(),
+            ));
+        }
+    }
+    burns
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use crate::metrics::create_registry_and_metrics;
+
+    use super::*;
+
+    fn results(latencies: &[Option<u64>]) -> VecDeque<(Instant, Option<Duration>)> {
+        latencies
+            .iter()
+            .map(|latency| (Instant::now(), latency.map(Duration::from_millis)))
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let objective = Objective {
+            latency: Some(Duration::from_secs(2)),
+            latency_percentile: 0.5,
+            success_rate: Some(0.9),
+        };
+        let burns = evaluate(
+            &objective,
+            &results(&[Some(1000), Some(3000), None, Some(2000), Some(1000)]),
+        );
+        assert_eq!(burns.len(), 2);
+        assert_eq!(burns[0].0, ObjectiveKind::SuccessRate);
+        assert!((burns[0].1 - 2.0).abs() < 1e-9);
+        assert_eq!(burns[1], (ObjectiveKind::Latency, 0.5));
+
+        let burns = evaluate(&objective, &results(&[None]));
+        assert_eq!(burns.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_on_breach_and_recovery() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                sender.send(body).unwrap();
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let objective = Objective {
+            success_rate: Some(0.5),
+            ..Default::default()
+        };
+        let monitor = SloMonitor::new(
+            HashMap::from([(TransactionKind::Swap, objective)]),
+            Duration::from_secs(3600),
+            2,
+            Some(Webhook {
+                url,
+                format: WebhookFormat::Pagerduty,
+                routing_key: Some("key".to_string()),
+            }),
+        );
+        let (_registry, metrics) = create_registry_and_metrics();
+        let labels = Labels::new(
+            "swap".to_string(),
+            String::new(),
+            "testnet".to_string(),
+            "eu".to_string(),
+        );
+        let burn_labels = SloLabels::new("success_rate".to_string(), labels.clone());
+
+        // A single failure is not enough samples to evaluate the objective.
+        monitor.record(&TransactionKind::Swap, &labels, &metrics, None);
+        assert_eq!(metrics.slo_burn.get_or_create(&burn_labels).get(), 0.0);
+        assert!(receiver.try_recv().is_err());
+
+        monitor.record(&TransactionKind::Swap, &labels, &metrics, None);
+        assert_eq!(metrics.slo_burn.get_or_create(&burn_labels).get(), 2.0);
+        let alert = receiver.recv().await.unwrap();
+        assert_eq!(alert["event_action"], "trigger");
+        assert_eq!(alert["routing_key"], "key");
+
+        // Still breached, no new alert is expected until the burn rate goes back to 1.
+        for _ in 0..3 {
+            monitor.record(
+                &TransactionKind::Swap,
+                &labels,
+                &metrics,
+                Some(Duration::ZERO),
+            );
+        }
+        assert_eq!(metrics.slo_burn.get_or_create(&burn_labels).get(), 0.8);
+        let alert = receiver.recv().await.unwrap();
+        assert_eq!(alert["event_action"], "resolve");
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,60 @@
+//! Module to set up logging and, optionally, export of traces through OTLP.
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+const SERVICE_NAME: &str = "transaction-bench";
+
+/// Guard returned by [`setup_tracing`]. Flushes pending spans to the collector when dropped.
+pub struct TracingGuard {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(err) = provider.shutdown() {
+                eprintln!("failed to shut down tracer provider: {err}");
+            }
+        }
+    }
+}
+
+/// Installs the global tracing subscriber. Logs are always printed to `stdout`; spans are
+/// additionally exported to `otlp_endpoint` (OTLP over HTTP) if it is set.
+pub fn setup_tracing(otlp_endpoint: Option<&str>, location: &str) -> anyhow::Result<TracingGuard> {
+    let fmt_layer = fmt::layer().with_target(false);
+    let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
+
+    let provider = otlp_endpoint
+        .map(|endpoint| create_tracer_provider(endpoint, location))
+        .transpose()?;
+    let otel_layer = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .init();
+    Ok(TracingGuard { provider })
+}
+
+fn create_tracer_provider(endpoint: &str, location: &str) -> anyhow::Result<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([
+            KeyValue::new("service.name", SERVICE_NAME),
+            KeyValue::new("location", location.to_string()),
+        ]))
+        .build())
+}
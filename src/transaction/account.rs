@@ -0,0 +1,654 @@
+use crate::config::Opts;
+use crate::TransactionSample;
+use async_trait::async_trait;
+use near_crypto::{KeyType, PublicKey, SecretKey};
+use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_jsonrpc_primitives::types::query::{QueryResponseKind, RpcQueryError};
+use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
+use near_primitives::account::{AccessKey, AccessKeyPermission, FunctionCallPermission};
+use near_primitives::action::{
+    AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction, TransferAction,
+};
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::transaction::{Action, Transaction, TransactionV0};
+use near_primitives::types::{AccountId, Balance, BlockReference, Nonce};
+use near_primitives::views::{
+    AccessKeyPermissionView, ActionView, FinalExecutionOutcomeView, QueryRequest,
+};
+use serde_json::json;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::info;
+
+use crate::metrics::{Labels, Metrics};
+
+use super::signer::{self, sign_transaction, signer_for, Signer};
+use super::{access_key_nonce, is_transaction_successful, TransactionKind, Variant};
+
+/// Balance of the created accounts, returned to the signer when they are deleted.
+const ACCOUNT_BALANCE: Balance = 10_000_000_000_000_000_000_000; // 0.01 NEAR
+const CREATE: &str = "create";
+const DELETE: &str = "delete";
+const ADD_KEY: &str = "add-key";
+const DELETE_KEY: &str = "delete-key";
+/// Number of sub-accounts that can exist at once. They are named after their slot, so that the
+/// ones left by a previous run are found again and deleted at startup.
+const ACCOUNT_SLOTS: usize = 16;
+/// Maximum length of the location in the names of sub-accounts and keys.
+const MAX_TAG_LEN: usize = 16;
+
+fn create_delete_variants(create: &str, delete: &str) -> Vec<Variant> {
+    [create, delete]
+        .into_iter()
+        .map(|label| Variant {
+            label: label.to_string(),
+            params: json!({}),
+        })
+        .collect()
+}
+
+/// Creates a sub-account of the signer, then deletes it with the signer as beneficiary. The
+/// sub-account gets the public key of the signer, so that it can be deleted with the same key.
+#[derive(Default)]
+pub struct AccountCreateDelete {
+    /// Accounts created successfully and not deleted yet.
+    created: Mutex<Vec<AccountId>>,
+}
+
+#[async_trait]
+impl TransactionSample for AccountCreateDelete {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::AccountCreateDelete
+    }
+
+    fn get_name(&self) -> &str {
+        "create and delete a sub-account"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn variants(&self, _opts: &Opts) -> Vec<Variant> {
+        create_delete_variants(CREATE, DELETE)
+    }
+
+    async fn clean_up(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()> {
+        let signer = signer::signer(opts);
+        for slot in 0..ACCOUNT_SLOTS {
+            let account_id = sub_account(opts, slot);
+            let account_signer = signer_for(opts, account_id.clone());
+            let Some(nonce) =
+                view_access_key_nonce(rpc_client, &account_id, account_signer.public_key()).await?
+            else {
+                continue;
+            };
+            let transaction = Transaction::V0(TransactionV0 {
+                signer_id: account_id.clone(),
+                public_key: account_signer.public_key(),
+                nonce: nonce + 1,
+                receiver_id: account_id.clone(),
+                block_hash,
+                actions: vec![Action::DeleteAccount(DeleteAccountAction {
+                    beneficiary_id: signer.account_id().clone(),
+                })],
+            });
+            let transaction = sign_transaction(account_signer.as_ref(), transaction).await?;
+            send_clean_up(rpc_client, transaction).await?;
+            info!("deleted sub-account {} left by a previous run", account_id);
+        }
+        Ok(())
+    }
+
+    async fn prepare(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        if variant.label != DELETE {
+            let created = self.created.lock().expect("created accounts lock poisoned");
+            let account_id = (0..ACCOUNT_SLOTS)
+                .map(|slot| sub_account(opts, slot))
+                .find(|account_id| !created.contains(account_id))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("{} sub-accounts left to delete first", ACCOUNT_SLOTS)
+                })?;
+            return Ok(Variant {
+                label: variant.label.clone(),
+                params: json!({ "account_id": account_id }),
+            });
+        }
+        // Accounts are forgotten only once deleted, so failed deletions are retried. Accounts
+        // deleted by a transaction whose result was lost no longer exist and are skipped.
+        loop {
+            let account_id = self
+                .created
+                .lock()
+                .expect("created accounts lock poisoned")
+                .last()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("no created account left to delete"))?;
+            let signer = signer_for(opts, account_id.clone());
+            match view_access_key_nonce(rpc_client, &account_id, signer.public_key()).await? {
+                Some(nonce) => {
+                    return Ok(Variant {
+                        label: variant.label.clone(),
+                        params: json!({ "account_id": account_id, "nonce": nonce }),
+                    })
+                }
+                None => remove(&self.created, &account_id),
+            }
+        }
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        // The created account signs its own deletion, with the key of the signer.
+        let account_signer;
+        let account_id: AccountId = variant.params["account_id"]
+            .as_str()
+            .and_then(|account_id| account_id.parse().ok())
+            .expect("set in prepare");
+        let (signer, nonce, receiver_id, actions) = if variant.label == DELETE {
+            let nonce = variant.params["nonce"].as_u64().unwrap_or_default();
+            let actions = vec![Action::DeleteAccount(DeleteAccountAction {
+                beneficiary_id: signer.account_id().clone(),
+            })];
+            account_signer = signer_for(&opts, account_id.clone());
+            (account_signer.as_ref(), nonce, account_id, actions)
+        } else {
+            let actions = vec![
+                Action::CreateAccount(CreateAccountAction {}),
+                Action::AddKey(Box::new(AddKeyAction {
+                    public_key: signer.public_key(),
+                    access_key: AccessKey::full_access(),
+                })),
+                Action::Transfer(TransferAction {
+                    deposit: ACCOUNT_BALANCE,
+                }),
+            ];
+            (signer, nonce, account_id, actions)
+        };
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id,
+            block_hash,
+            actions,
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+
+    async fn record_outcome(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        _metrics: &Metrics,
+        _labels: &Labels,
+        _sent_at: SystemTime,
+        outcome: &FinalExecutionOutcomeView,
+    ) {
+        let transaction = &outcome.transaction;
+        for action in &transaction.actions {
+            match action {
+                ActionView::CreateAccount => self
+                    .created
+                    .lock()
+                    .expect("created accounts lock poisoned")
+                    .push(transaction.receiver_id.clone()),
+                ActionView::DeleteAccount { .. } => remove(&self.created, &transaction.signer_id),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Adds a random function call access key to the signer, then deletes it.
+#[derive(Default)]
+pub struct AccessKeyAddDelete {
+    /// Keys added successfully and not deleted yet.
+    added: Mutex<Vec<PublicKey>>,
+}
+
+#[async_trait]
+impl TransactionSample for AccessKeyAddDelete {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::AccessKeyAddDelete
+    }
+
+    fn get_name(&self) -> &str {
+        "add and delete an access key of the signer"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn variants(&self, _opts: &Opts) -> Vec<Variant> {
+        create_delete_variants(ADD_KEY, DELETE_KEY)
+    }
+
+    async fn clean_up(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()> {
+        let signer = signer::signer(opts);
+        let response = rpc_client
+            .call(methods::query::RpcQueryRequest {
+                block_reference: BlockReference::latest(),
+                request: QueryRequest::ViewAccessKeyList {
+                    account_id: signer.account_id().clone(),
+                },
+            })
+            .await?;
+        let QueryResponseKind::AccessKeyList(keys) = response.kind else {
+            anyhow::bail!("could not retrieve access keys of {}", signer.account_id());
+        };
+        let marker = key_marker(opts);
+        let actions: Vec<Action> = keys
+            .keys
+            .into_iter()
+            .filter(|key| match &key.access_key.permission {
+                AccessKeyPermissionView::FunctionCall {
+                    receiver_id,
+                    method_names,
+                    ..
+                } => {
+                    *receiver_id == signer.account_id().as_str()
+                        && *method_names == [marker.as_str()]
+                }
+                AccessKeyPermissionView::FullAccess => false,
+            })
+            .map(|key| {
+                Action::DeleteKey(Box::new(DeleteKeyAction {
+                    public_key: key.public_key,
+                }))
+            })
+            .collect();
+        if actions.is_empty() {
+            return Ok(());
+        }
+        let count = actions.len();
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: access_key_nonce(rpc_client, signer.as_ref()).await? + 1,
+            receiver_id: signer.account_id().clone(),
+            block_hash,
+            actions,
+        });
+        let transaction = sign_transaction(signer.as_ref(), transaction).await?;
+        send_clean_up(rpc_client, transaction).await?;
+        info!("deleted {} access keys left by a previous run", count);
+        Ok(())
+    }
+
+    async fn prepare(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        if variant.label != DELETE_KEY {
+            return Ok(variant.clone());
+        }
+        // As for accounts, keys are forgotten only once deleted, or found to be gone.
+        loop {
+            let public_key = self
+                .added
+                .lock()
+                .expect("added keys lock poisoned")
+                .last()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("no added key left to delete"))?;
+            match view_access_key_nonce(rpc_client, &opts.signer_id, public_key.clone()).await? {
+                Some(_) => {
+                    return Ok(Variant {
+                        label: variant.label.clone(),
+                        params: json!({ "public_key": public_key }),
+                    })
+                }
+                None => remove(&self.added, &public_key),
+            }
+        }
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let action = if variant.label == DELETE_KEY {
+            Action::DeleteKey(Box::new(DeleteKeyAction {
+                public_key: serde_json::from_value(variant.params["public_key"].clone())
+                    .expect("set in prepare"),
+            }))
+        } else {
+            // The secret key is dropped, the key is only used to measure the latency of `AddKey`.
+            // Its method name marks it as added by the benchmark, to be found after a restart.
+            Action::AddKey(Box::new(AddKeyAction {
+                public_key: SecretKey::from_random(KeyType::ED25519).public_key(),
+                access_key: AccessKey {
+                    nonce: 0,
+                    permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                        allowance: None,
+                        receiver_id: signer.account_id().to_string(),
+                        method_names: vec![key_marker(&opts)],
+                    }),
+                },
+            }))
+        };
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id: signer.account_id().clone(),
+            block_hash,
+            actions: vec![action],
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+
+    async fn record_outcome(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        _metrics: &Metrics,
+        _labels: &Labels,
+        _sent_at: SystemTime,
+        outcome: &FinalExecutionOutcomeView,
+    ) {
+        for action in &outcome.transaction.actions {
+            match action {
+                ActionView::AddKey { public_key, .. } => self
+                    .added
+                    .lock()
+                    .expect("added keys lock poisoned")
+                    .push(public_key.clone()),
+                ActionView::DeleteKey { public_key } => remove(&self.added, public_key),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Forgets `item`, deleted successfully or found to be gone.
+fn remove<T: PartialEq>(items: &Mutex<Vec<T>>, item: &T) {
+    items
+        .lock()
+        .expect("tracked items lock poisoned")
+        .retain(|tracked| tracked != item);
+}
+
+/// Returns the nonce of the access key `public_key` of `account_id`, or `None` if the account
+/// or the key does not exist.
+async fn view_access_key_nonce(
+    rpc_client: &JsonRpcClient,
+    account_id: &AccountId,
+    public_key: PublicKey,
+) -> anyhow::Result<Option<Nonce>> {
+    let response = rpc_client
+        .call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccessKey {
+                account_id: account_id.clone(),
+                public_key,
+            },
+        })
+        .await;
+    match response {
+        Ok(response) => match response.kind {
+            QueryResponseKind::AccessKey(access_key) => Ok(Some(access_key.nonce)),
+            _ => anyhow::bail!("could not retrieve access key of {}", account_id),
+        },
+        Err(err) => match err.handler_error() {
+            Some(RpcQueryError::UnknownAccount { .. } | RpcQueryError::UnknownAccessKey { .. }) => {
+                Ok(None)
+            }
+            _ => Err(err.into()),
+        },
+    }
+}
+
+/// Sends a transaction deleting what a previous run left, and waits for its execution.
+async fn send_clean_up(
+    rpc_client: &JsonRpcClient,
+    signed_transaction: SignedTransaction,
+) -> anyhow::Result<()> {
+    let response = rpc_client
+        .call(RpcSendTransactionRequest {
+            signed_transaction,
+            wait_until: Default::default(),
+        })
+        .await?;
+    anyhow::ensure!(
+        is_transaction_successful(&response),
+        "clean up transaction failed"
+    );
+    Ok(())
+}
+
+/// Returns the location, restricted to lowercase letters and digits, which tells apart the
+/// sub-accounts and keys of benchmark instances sharing the signer.
+fn instance_tag(opts: &Opts) -> String {
+    opts.location
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .take(MAX_TAG_LEN)
+        .collect()
+}
+
+/// Returns the sub-account of the signer created in `slot`, `txb-<location>-<slot>`.
+fn sub_account(opts: &Opts, slot: usize) -> AccountId {
+    let tag = instance_tag(opts);
+    let name = match tag.is_empty() {
+        true => format!("txb-{}", slot),
+        false => format!("txb-{}-{}", tag, slot),
+    };
+    format!("{}.{}", name, opts.signer_id)
+        .parse()
+        .expect("sub-account of a valid account id")
+}
+
+/// Returns the method name allowed by the keys added by the benchmark, `tx_bench_<location>`.
+fn key_marker(opts: &Opts) -> String {
+    let tag = instance_tag(opts);
+    match tag.is_empty() {
+        true => "tx_bench".to_string(),
+        false => format!("tx_bench_{}", tag),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::views::{ExecutionStatusView, SignedTransactionView};
+
+    use tokio::sync::mpsc;
+
+    use crate::metrics::create_registry_and_metrics;
+    use crate::transaction::test_utils::{
+        create_test_final_outcome, create_test_outcome, create_test_request, create_test_run_opts,
+        create_test_tx_result, start_test_rpc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_sub_account() {
+        let opts = Opts {
+            location: "EU West-1".to_string(),
+            ..create_test_run_opts()
+        };
+        let account_id = sub_account(&opts, 3);
+        assert_eq!(account_id.as_str(), "txb-euwest1-3.cat.near");
+        assert!(account_id.is_sub_account_of(&opts.signer_id));
+        assert_eq!(key_marker(&opts), "tx_bench_euwest1");
+
+        let opts = Opts {
+            location: "-".to_string(),
+            ..opts
+        };
+        assert_eq!(sub_account(&opts, 0).as_str(), "txb-0.cat.near");
+        assert_eq!(key_marker(&opts), "tx_bench");
+    }
+
+    #[tokio::test]
+    async fn test_create_delete_bookkeeping() {
+        let sample = AccountCreateDelete::default();
+        let rpc_client = JsonRpcClient::connect("http://127.0.0.1:1");
+        let (_registry, metrics) = create_registry_and_metrics();
+        let labels = Labels::new(
+            "account-create-delete".to_string(),
+            CREATE.to_string(),
+            "localnet".to_string(),
+            "eu".to_string(),
+        );
+        let request = create_test_request(vec![Action::CreateAccount(CreateAccountAction {})]);
+        let mut outcome = create_test_final_outcome(
+            create_test_outcome(
+                CryptoHash::new(),
+                CryptoHash::new(),
+                0,
+                ExecutionStatusView::SuccessValue(vec![]),
+            ),
+            vec![],
+        );
+        outcome.transaction = SignedTransactionView::from(request.signed_transaction);
+        sample
+            .record_outcome(&rpc_client, &metrics, &labels, SystemTime::now(), &outcome)
+            .await;
+        let account_id: AccountId = "frog.near".parse().unwrap();
+        assert_eq!(*sample.created.lock().unwrap(), vec![account_id.clone()]);
+
+        // The account is kept when the deletion cannot even be prepared, to be retried later.
+        let delete = Variant {
+            label: DELETE.to_string(),
+            params: json!({}),
+        };
+        assert!(sample
+            .prepare(&rpc_client, &create_test_run_opts(), &delete)
+            .await
+            .is_err());
+        assert_eq!(*sample.created.lock().unwrap(), vec![account_id.clone()]);
+
+        // It is forgotten once deleted.
+        let request = create_test_request(vec![Action::DeleteAccount(DeleteAccountAction {
+            beneficiary_id: "cat.near".parse().unwrap(),
+        })]);
+        outcome.transaction = SignedTransactionView::from(request.signed_transaction);
+        outcome.transaction.signer_id = account_id;
+        sample
+            .record_outcome(&rpc_client, &metrics, &labels, SystemTime::now(), &outcome)
+            .await;
+        assert!(sample.created.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clean_up() {
+        let opts = create_test_run_opts();
+        let signer = signer::signer(&opts);
+        let leftovers = [sub_account(&opts, 0), sub_account(&opts, 5)];
+        let marked = SecretKey::from_random(KeyType::ED25519).public_key();
+        let other = SecretKey::from_random(KeyType::ED25519).public_key();
+        let key = |public_key: &PublicKey, method_names: Vec<String>| {
+            json!({
+                "public_key": public_key,
+                "access_key": {"nonce": 0, "permission": {"FunctionCall": {
+                    "allowance": null,
+                    "receiver_id": opts.signer_id,
+                    "method_names": method_names,
+                }}},
+            })
+        };
+        let keys = json!([key(&marked, vec![key_marker(&opts)]), key(&other, vec![]),]);
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let existing = leftovers.clone();
+        let url = start_test_rpc(move |method, params| {
+            let block = json!({"block_height": 1, "block_hash": CryptoHash::new().to_string()});
+            if method == "send_tx" {
+                let request: RpcSendTransactionRequest =
+                    serde_json::from_value(params.clone()).unwrap();
+                sender.send(request.signed_transaction.clone()).unwrap();
+                return Ok(create_test_tx_result(&request, vec![]));
+            }
+            let account_id: AccountId = params["account_id"].as_str().unwrap().parse().unwrap();
+            let mut result = match params["request_type"].as_str().unwrap() {
+                "view_access_key_list" => json!({ "keys": keys }),
+                _ if existing.contains(&account_id) || account_id == "cat.near" => {
+                    json!({"nonce": 7, "permission": "FullAccess"})
+                }
+                _ => {
+                    return Err(json!({
+                        "name": "HANDLER_ERROR",
+                        "cause": {"name": "UNKNOWN_ACCOUNT", "info": {
+                            "requested_account_id": account_id,
+                            "block_height": 1,
+                            "block_hash": CryptoHash::new().to_string(),
+                        }},
+                        "code": -32000,
+                        "message": "Server error",
+                    }))
+                }
+            };
+            result
+                .as_object_mut()
+                .unwrap()
+                .extend(block.as_object().unwrap().clone());
+            Ok(result)
+        })
+        .await;
+        let rpc_client = JsonRpcClient::connect(url);
+
+        AccountCreateDelete::default()
+            .clean_up(&rpc_client, &opts, CryptoHash::new())
+            .await
+            .unwrap();
+        for account_id in &leftovers {
+            let transaction = receiver.try_recv().unwrap().transaction;
+            assert_eq!(transaction.signer_id(), account_id);
+            assert_eq!(transaction.nonce(), 8);
+            assert!(matches!(
+                transaction.actions(),
+                [Action::DeleteAccount(DeleteAccountAction { beneficiary_id })]
+                    if *beneficiary_id == opts.signer_id
+            ));
+        }
+        assert!(receiver.try_recv().is_err());
+
+        AccessKeyAddDelete::default()
+            .clean_up(&rpc_client, &opts, CryptoHash::new())
+            .await
+            .unwrap();
+        let transaction = receiver.try_recv().unwrap().transaction;
+        assert_eq!(transaction.public_key(), &signer.public_key());
+        assert!(matches!(
+            transaction.actions(),
+            [Action::DeleteKey(delete)] if delete.public_key == marked
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,333 @@
+use crate::config::{BatchAction, Opts};
+use crate::metrics::{Labels, Metrics};
+use crate::transaction::token_transfer_default::TokenTransferDefault;
+use crate::TransactionSample;
+use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use near_jsonrpc_client::JsonRpcClient;
+use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
+use near_primitives::action::{FunctionCallAction, TransferAction};
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, Transaction, TransactionV0};
+use near_primitives::types::Nonce;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info_span, Instrument};
+
+use super::nonces::{send_in_order, Nonces};
+use super::signer::{self, sign_transaction, Signer};
+use super::{TransactionKind, Variant};
+
+fn batch_variants(prefix: &str, sizes: &[usize]) -> Vec<Variant> {
+    sizes
+        .iter()
+        .map(|size| Variant {
+            label: format!("{}-{}", prefix, size),
+            params: json!({ "size": size }),
+        })
+        .collect()
+}
+
+fn batch_size(variant: &Variant) -> usize {
+    variant.params["size"].as_u64().unwrap_or(1) as usize
+}
+
+/// A single transaction with a batch of transfers or FT transfers.
+pub struct BatchActions {}
+
+#[async_trait]
+impl TransactionSample for BatchActions {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::BatchActions
+    }
+
+    fn get_name(&self) -> &str {
+        "transaction with a batch of actions"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        batch_variants("actions", &opts.batch_sizes)
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let size = batch_size(variant);
+        let (receiver_id, action) = match opts.batch_action {
+            BatchAction::Transfer => (
+                opts.receiver_id,
+                Action::Transfer(TransferAction { deposit: 1 }),
+            ),
+            BatchAction::FunctionCall => (
+                opts.ft_account_id,
+                Action::FunctionCall(Box::new(FunctionCallAction {
+                    method_name: "ft_transfer".to_string(),
+                    args: json!({"amount": "1", "receiver_id": opts.receiver_id})
+                        .to_string()
+                        .into_bytes(),
+                    gas: variant.function_calls_gas(100_000_000_000_000, size),
+                    deposit: variant.function_call_deposit(1),
+                })),
+            ),
+        };
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id,
+            block_hash,
+            actions: vec![action; size],
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+}
+
+/// A batch of `TokenTransferDefault` transactions sent in nonce order, at most `SEND_ORDER_DELAY`
+/// apart, so that they are likely to be included in the same block. The latency is the one of
+/// the slowest transaction.
+pub struct BatchTransactions {}
+
+#[async_trait]
+impl TransactionSample for BatchTransactions {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::BatchTransactions
+    }
+
+    fn get_name(&self) -> &str {
+        "batch of transactions sent at once"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        batch_variants("txs", &opts.batch_sizes)
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        _variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        TokenTransferDefault {}
+            .get_transaction_request(signer, opts, &Variant::default(), nonce, block_hash)
+            .await
+    }
+
+    async fn execute(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: Opts,
+        variant: &Variant,
+        metrics: &Arc<Metrics>,
+        labels: &Labels,
+        nonces: &Nonces,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<Duration> {
+        let size = batch_size(variant);
+        let signer = signer::signer(&opts);
+        // Every transaction is signed before the first one is sent, so that sending them takes
+        // less than a block, with the key locked as for a single transaction.
+        let (first_nonce, key_guard) = nonces.reserve_many(size).await;
+        let signing_started = Instant::now();
+        let requests = try_join_all((0..size).map(|i| {
+            self.get_transaction_request(
+                signer.as_ref(),
+                opts.clone(),
+                variant,
+                first_nonce + i as Nonce,
+                block_hash,
+            )
+        }))
+        .instrument(info_span!("sign"))
+        .await?;
+        metrics
+            .transaction_signing_latency
+            .get_or_create(labels)
+            .observe(signing_started.elapsed().as_secs_f64());
+
+        // All the transactions are awaited, whether or not the others fail.
+        let sends = requests.iter().map(|request| {
+            self.send_transaction(rpc_client, &opts, variant, metrics, labels, nonces, request)
+        });
+        let results = send_in_order(join_all(sends), key_guard).await;
+        let mut latency = Duration::ZERO;
+        let mut errors = vec![];
+        for result in results {
+            match result {
+                Ok(elapsed) => latency = latency.max(elapsed),
+                Err(err) => {
+                    metrics
+                        .batch_failed_transactions
+                        .get_or_create(labels)
+                        .inc();
+                    errors.push(err);
+                }
+            }
+        }
+        match errors.len() {
+            0 => Ok(latency),
+            // The error is kept as is, so that a batch that expired or used outdated nonces is
+            // retried as a whole.
+            failed if failed == size => Err(errors.swap_remove(0)),
+            failed => Err(anyhow::anyhow!(
+                "{} failed: {} of {} transactions failed, first error: {}",
+                self.get_name(),
+                failed,
+                size,
+                errors[0]
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::InMemorySigner;
+
+    use crate::config::{FunctionCallOverwrite, GasSetting};
+    use crate::gas::MAX_GAS;
+    use crate::metrics::{create_registry_and_metrics, Labels};
+    use crate::transaction::test_utils::{
+        create_test_run_opts, create_test_tx_result, start_test_rpc,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_batch_actions() {
+        let mut opts = create_test_run_opts();
+        opts.batch_action = BatchAction::FunctionCall;
+        let signer = InMemorySigner::from_secret_key(opts.signer_id.clone(), opts.signer_key());
+        let variant = &batch_variants("actions", &[10])[0];
+        assert_eq!(variant.label, "actions-10");
+        let request = BatchActions {}
+            .get_transaction_request(&signer, opts, variant, 1, CryptoHash::new())
+            .await
+            .unwrap();
+        let actions = request.signed_transaction.transaction.actions();
+        assert_eq!(actions.len(), 10);
+        let Action::FunctionCall(call) = &actions[0] else {
+            panic!("expected a function call");
+        };
+        assert_eq!(call.gas, 30_000_000_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_batch_actions_gas_within_limit() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let mut opts = create_test_run_opts();
+        opts.batch_action = BatchAction::FunctionCall;
+        let signer = InMemorySigner::from_secret_key(opts.signer_id.clone(), opts.signer_key());
+        let labels = Labels::new(
+            "batch-actions".to_string(),
+            "actions-10".to_string(),
+            "mainnet".to_string(),
+            "eu".to_string(),
+        );
+        metrics.gas_estimator.record(&labels, 100_000_000_000_000);
+        let overwrites = [
+            (GasSetting::Auto, 15_000_000_000_000),
+            (GasSetting::Fixed(100_000_000_000_000), MAX_GAS / 10),
+        ];
+        for (setting, expected) in overwrites {
+            let overwrite = FunctionCallOverwrite {
+                gas: Some(setting),
+                deposit: None,
+            };
+            let variant = batch_variants("actions", &[10])[0]
+                .clone()
+                .with_function_call_overwrite(&overwrite, &metrics.gas_estimator, &labels, 0.5);
+            let request = BatchActions {}
+                .get_transaction_request(&signer, opts.clone(), &variant, 1, CryptoHash::new())
+                .await
+                .unwrap();
+            let actions = request.signed_transaction.transaction.actions();
+            assert_eq!(actions.len(), 10);
+            let gas: Vec<_> = actions
+                .iter()
+                .map(|action| match action {
+                    Action::FunctionCall(call) => call.gas,
+                    _ => panic!("expected a function call"),
+                })
+                .collect();
+            assert!(gas.iter().all(|gas| *gas == expected));
+            assert!(gas.iter().sum::<u64>() <= MAX_GAS);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_transactions_counts_failures() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let opts = create_test_run_opts();
+        let labels = Labels::new(
+            "batch-transactions".to_string(),
+            "txs-3".to_string(),
+            "localnet".to_string(),
+            "eu".to_string(),
+        );
+        let received = Arc::new(std::sync::Mutex::new(vec![]));
+        let url = start_test_rpc({
+            let received = received.clone();
+            move |method, params| {
+                assert_eq!(method, "send_tx");
+                let request: RpcSendTransactionRequest =
+                    serde_json::from_value(params.clone()).unwrap();
+                let nonce = request.signed_transaction.transaction.nonce();
+                received.lock().unwrap().push(nonce);
+                if nonce == 1 {
+                    return Err(json!({
+                        "name": "INTERNAL_ERROR",
+                        "cause": {"name": "INTERNAL_ERROR", "info": {"error_message": "test"}},
+                        "code": -32000,
+                        "message": "Server error",
+                    }));
+                }
+                Ok(create_test_tx_result(&request, vec![]))
+            }
+        })
+        .await;
+
+        let err = BatchTransactions {}
+            .execute(
+                &JsonRpcClient::connect(url),
+                opts,
+                &batch_variants("txs", &[3])[0],
+                &metrics,
+                &labels,
+                &Nonces::default(),
+                CryptoHash::new(),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("1 of 3 transactions failed"));
+        assert_eq!(
+            metrics
+                .batch_failed_transactions
+                .get_or_create(&labels)
+                .get(),
+            1
+        );
+        // The failure of the first transaction did not stop the others.
+        let mut received = received.lock().unwrap().clone();
+        received.sort();
+        assert_eq!(received, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Background tracking of a recent final block, referenced by the transactions of every sample.
+
+use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, BlockReference, Finality};
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::time::{interval, Instant};
+use tracing::{debug, instrument, warn};
+
+#[derive(Debug, Clone, Copy)]
+struct TrackedBlock {
+    hash: CryptoHash,
+    height: BlockHeight,
+    fetched_at: Instant,
+}
+
+/// Keeps the hash of a recent final block, polled every `poll_interval`. Blocks fetched more
+/// than `max_age` ago are considered expired and refreshed on demand, so transactions are never
+/// signed with a stale reference when polling fails.
+pub(crate) struct BlockTracker {
+    rpc_client: JsonRpcClient,
+    poll_interval: Duration,
+    max_age: Duration,
+    latest: RwLock<Option<TrackedBlock>>,
+}
+
+impl BlockTracker {
+    pub fn new(rpc_client: JsonRpcClient, poll_interval: Duration, max_age: Duration) -> Self {
+        Self {
+            rpc_client,
+            poll_interval,
+            max_age,
+            latest: RwLock::new(None),
+        }
+    }
+
+    /// Polls the latest final block until the program is stopped. Failed polls are logged and
+    /// retried at the next tick.
+    pub async fn run(&self) {
+        let mut interval = interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.refresh().await {
+                warn!("failed to fetch latest final block: {}", err);
+            }
+        }
+    }
+
+    /// Returns the hash of the tracked block, fetching a new one if it expired.
+    pub async fn block_hash(&self) -> anyhow::Result<CryptoHash> {
+        let latest = *self.latest.read().expect("block tracker lock poisoned");
+        match latest {
+            Some(block) if block.fetched_at.elapsed() <= self.max_age => Ok(block.hash),
+            _ => self.refresh().await,
+        }
+    }
+
+    /// Forgets the tracked block, e.g. after a transaction referencing it expired.
+    pub fn invalidate(&self) {
+        *self.latest.write().expect("block tracker lock poisoned") = None;
+    }
+
+    #[instrument(name = "fetch_block", skip_all)]
+    async fn refresh(&self) -> anyhow::Result<CryptoHash> {
+        let block = self
+            .rpc_client
+            .call(methods::block::RpcBlockRequest {
+                block_reference: BlockReference::Finality(Finality::Final),
+            })
+            .await?;
+        self.update(block.header.hash, block.header.height);
+        Ok(block.header.hash)
+    }
+
+    /// Tracks the given block, unless a higher one is already tracked.
+    pub fn update(&self, hash: CryptoHash, height: BlockHeight) {
+        let mut latest = self.latest.write().expect("block tracker lock poisoned");
+        if latest.is_some_and(|block| block.height > height) {
+            return;
+        }
+        debug!("tracking block {} at height {}", hash, height);
+        *latest = Some(TrackedBlock {
+            hash,
+            height,
+            fetched_at: Instant::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_tracker(max_age: Duration) -> BlockTracker {
+        // Nothing listens on the discard port, fetching a block always fails.
+        BlockTracker::new(
+            JsonRpcClient::connect("http://127.0.0.1:9"),
+            Duration::from_secs(1),
+            max_age,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_is_cached_until_invalidated() {
+        let tracker = create_test_tracker(Duration::from_secs(3600));
+        assert!(tracker.block_hash().await.is_err());
+
+        let hash = CryptoHash::hash_bytes(b"block");
+        tracker.update(hash, 10);
+        tracker.update(CryptoHash::new(), 9);
+        assert_eq!(tracker.block_hash().await.unwrap(), hash);
+
+        tracker.invalidate();
+        assert!(tracker.block_hash().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expired_block_is_refreshed() {
+        let tracker = create_test_tracker(Duration::ZERO);
+        tracker.update(CryptoHash::new(), 10);
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert!(tracker.block_hash().await.is_err());
+    }
+}
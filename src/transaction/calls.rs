@@ -0,0 +1,124 @@
+use crate::config::Opts;
+use crate::TransactionSample;
+use async_trait::async_trait;
+use near_jsonrpc_client::JsonRpcClient;
+use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
+use near_primitives::action::FunctionCallAction;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, Transaction, TransactionV0};
+use near_primitives::types::{AccountId, Nonce};
+use serde_json::json;
+
+use super::signer::{sign_transaction, Signer};
+use super::{TransactionKind, Variant};
+
+/// Calls the `res/calls.wasm` contract, which makes a chain of cross-contract calls as deep as
+/// the configured depth, or a number of parallel calls. The calls go through all the configured
+/// contract accounts in turn, so that they cross shards if the accounts are on different shards.
+pub struct CallDepth {}
+
+#[async_trait]
+impl TransactionSample for CallDepth {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::CallDepth
+    }
+
+    fn get_name(&self) -> &str {
+        "cross-contract call chain"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        let chains = opts.call_depths.iter().map(|depth| Variant {
+            label: format!("depth-{}", depth),
+            params: json!({ "method": "chain", "calls": depth }),
+        });
+        let fan_outs = opts.call_fan_outs.iter().map(|calls| Variant {
+            label: format!("fan-out-{}", calls),
+            params: json!({ "method": "fan_out", "calls": calls }),
+        });
+        chains.chain(fan_outs).collect()
+    }
+
+    async fn prepare(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        anyhow::ensure!(
+            !opts.call_contract_ids.is_empty(),
+            "CALL_CONTRACT_IDS is required by cross-contract call transactions"
+        );
+        Ok(variant.clone())
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let calls = variant.params["calls"].as_u64().unwrap_or_default() as usize;
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id: opts.call_contract_ids[0].clone(),
+            block_hash,
+            actions: vec![Action::FunctionCall(Box::new(FunctionCallAction {
+                method_name: variant.params["method"]
+                    .as_str()
+                    .unwrap_or("chain")
+                    .to_string(),
+                args: encode_accounts(&callees(&opts.call_contract_ids, calls)),
+                gas: variant.function_call_gas(300_000_000_000_000), // 300 TeraGas
+                deposit: variant.function_call_deposit(0),
+            }))],
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+}
+
+/// Returns the accounts called by the first one, going through `contract_ids` in turn.
+fn callees(contract_ids: &[AccountId], calls: usize) -> Vec<&AccountId> {
+    contract_ids.iter().cycle().skip(1).take(calls).collect()
+}
+
+/// Encodes accounts as expected by the contract: each one prefixed by its length as a byte.
+fn encode_accounts(accounts: &[&AccountId]) -> Vec<u8> {
+    let mut args = vec![];
+    for account_id in accounts {
+        args.push(account_id.len() as u8);
+        args.extend_from_slice(account_id.as_bytes());
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callees() {
+        let contract_ids: Vec<AccountId> =
+            vec!["a.near".parse().unwrap(), "bb.near".parse().unwrap()];
+        let callees = callees(&contract_ids, 3);
+        assert_eq!(
+            callees,
+            vec![&contract_ids[1], &contract_ids[0], &contract_ids[1]]
+        );
+        assert_eq!(
+            encode_accounts(&callees),
+            b"\x07bb.near\x06a.near\x07bb.near".to_vec()
+        );
+    }
+}
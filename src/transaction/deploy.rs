@@ -0,0 +1,292 @@
+use crate::config::Opts;
+use crate::leb128::encode_varint;
+use crate::TransactionSample;
+use async_trait::async_trait;
+use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_jsonrpc_primitives::types::query::{QueryResponseKind, RpcQueryError};
+use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
+use near_primitives::account::AccessKey;
+use near_primitives::action::{
+    AddKeyAction, CreateAccountAction, DeployContractAction, FunctionCallAction, TransferAction,
+};
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, Transaction, TransactionV0};
+use near_primitives::types::{AccountId, Balance, BlockReference, Nonce};
+use near_primitives::views::QueryRequest;
+use rand::Rng;
+use serde_json::json;
+use std::sync::Arc;
+
+use super::signer::{sign_transaction, signer_for, Signer};
+use super::{TransactionKind, Variant};
+
+/// Contract exporting a single `ping` method that does nothing, see `res/ping.wat`.
+const PING_WASM: &[u8] = include_bytes!("../../res/ping.wasm");
+/// Cost of storage, in yoctoNEAR per byte.
+const STORAGE_PRICE_PER_BYTE: Balance = 10_000_000_000_000_000_000;
+/// Storage reserved on the deploy account besides the contract code.
+const STORAGE_OVERHEAD: usize = 1_000;
+/// Balance kept on the deploy account, besides its storage, to pay for the gas of deployments.
+const GAS_RESERVE: Balance = 100_000_000_000_000_000_000_000; // 0.1 NEAR
+const DEPLOY: &str = "deploy";
+const FIRST_CALL: &str = "first-call";
+
+/// Deploys a contract to a dedicated sub-account of the signer, then calls it. Every deployed
+/// contract is unique, so the first call always has to compile it.
+pub struct ContractDeploy {}
+
+#[async_trait]
+impl TransactionSample for ContractDeploy {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::ContractDeploy
+    }
+
+    fn get_name(&self) -> &str {
+        "deploy a contract and call it"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        opts.deploy_padding
+            .iter()
+            .flat_map(|padding| {
+                [DEPLOY, FIRST_CALL].map(|step| Variant {
+                    label: format!("{}/pad-{}", step, padding),
+                    params: json!({ "step": step, "padding": padding }),
+                })
+            })
+            .collect()
+    }
+
+    async fn prepare(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        let mut variant = variant.clone();
+        if variant.params["step"] != DEPLOY {
+            // The deploy account pays for the gas of every deployment, it is refilled by the
+            // signer along with the call once half of the gas reserve is spent.
+            let account_id = deploy_account_id(&opts.signer_id);
+            let target = target_balance(opts);
+            if let Some(balance) = account_balance(rpc_client, &account_id).await? {
+                if balance < target - GAS_RESERVE / 2 {
+                    variant.params["top_up"] = json!((target - balance).to_string());
+                }
+            }
+            return Ok(variant);
+        }
+        let signer = deploy_signer(&opts.signer_id, opts);
+        variant.params["nonce"] = json!(deploy_account_nonce(rpc_client, signer.as_ref()).await?);
+        Ok(variant)
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let account_id = deploy_account_id(signer.account_id());
+        let deploy_signer = deploy_signer(signer.account_id(), &opts);
+        let (signer, nonce, actions) = if variant.params["step"] == DEPLOY {
+            let padding = variant.params["padding"].as_u64().unwrap_or_default() as usize;
+            let deploy = Action::DeployContract(DeployContractAction {
+                code: contract_code(padding),
+            });
+            match variant.params["nonce"].as_u64() {
+                Some(deploy_nonce) => (deploy_signer.as_ref(), deploy_nonce, vec![deploy]),
+                // The account is created on first use, with enough balance for the largest code.
+                None => {
+                    let actions = vec![
+                        Action::CreateAccount(CreateAccountAction {}),
+                        Action::Transfer(TransferAction {
+                            deposit: target_balance(&opts),
+                        }),
+                        Action::AddKey(Box::new(AddKeyAction {
+                            public_key: signer.public_key(),
+                            access_key: AccessKey::full_access(),
+                        })),
+                        deploy,
+                    ];
+                    (signer, nonce, actions)
+                }
+            }
+        } else {
+            let mut actions = vec![Action::FunctionCall(Box::new(FunctionCallAction {
+                method_name: "ping".to_string(),
+                args: vec![],
+                gas: variant.function_call_gas(10_000_000_000_000), // 10 TeraGas
+                deposit: variant.function_call_deposit(0),
+            }))];
+            if let Some(top_up) = variant.params["top_up"]
+                .as_str()
+                .and_then(|top_up| top_up.parse().ok())
+            {
+                actions.push(Action::Transfer(TransferAction { deposit: top_up }));
+            }
+            (signer, nonce, actions)
+        };
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id: account_id,
+            block_hash,
+            actions,
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+}
+
+fn deploy_account_id(signer_id: &AccountId) -> AccountId {
+    format!("tx-bench-deploy.{}", signer_id)
+        .parse()
+        .expect("sub-account of a valid account id")
+}
+
+/// Returns the balance the deploy account is funded with: the storage of the largest code and
+/// the gas reserve.
+fn target_balance(opts: &Opts) -> Balance {
+    let max_padding = opts.deploy_padding.iter().max().copied().unwrap_or(0);
+    STORAGE_PRICE_PER_BYTE * (PING_WASM.len() + max_padding + STORAGE_OVERHEAD) as Balance
+        + GAS_RESERVE
+}
+
+/// Returns the balance of `account_id`, or `None` if it does not exist yet.
+async fn account_balance(
+    rpc_client: &JsonRpcClient,
+    account_id: &AccountId,
+) -> anyhow::Result<Option<Balance>> {
+    let response = rpc_client
+        .call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccount {
+                account_id: account_id.clone(),
+            },
+        })
+        .await;
+    match response {
+        Ok(response) => match response.kind {
+            QueryResponseKind::ViewAccount(account) => Ok(Some(account.amount)),
+            _ => anyhow::bail!("could not retrieve account {}", account_id),
+        },
+        Err(err) => match err.handler_error() {
+            Some(RpcQueryError::UnknownAccount { .. }) => Ok(None),
+            _ => Err(err.into()),
+        },
+    }
+}
+
+/// Returns the signer of transactions of the deploy account, which shares the signer key.
+fn deploy_signer(signer_id: &AccountId, opts: &Opts) -> Arc<dyn Signer> {
+    signer_for(opts, deploy_account_id(signer_id))
+}
+
+/// Returns the nonce of the key of the deploy account, or `None` if it does not exist yet.
+async fn deploy_account_nonce(
+    rpc_client: &JsonRpcClient,
+    signer: &dyn Signer,
+) -> anyhow::Result<Option<Nonce>> {
+    let response = rpc_client
+        .call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccessKey {
+                account_id: signer.account_id().clone(),
+                public_key: signer.public_key(),
+            },
+        })
+        .await;
+    match response {
+        Ok(response) => match response.kind {
+            QueryResponseKind::AccessKey(access_key) => Ok(Some(access_key.nonce)),
+            _ => anyhow::bail!("could not retrieve access key for {}", signer.account_id()),
+        },
+        Err(err) => match err.handler_error() {
+            Some(RpcQueryError::UnknownAccount { .. }) => Ok(None),
+            _ => Err(err.into()),
+        },
+    }
+}
+
+/// Returns the `ping` contract with a custom section of random bytes, making its hash unique.
+fn contract_code(padding: usize) -> Vec<u8> {
+    const SECTION_NAME: &[u8] = b"tx-bench";
+    let mut payload = vec![0u8; 16 + padding];
+    rand::thread_rng().fill(&mut payload[..]);
+
+    let mut section = vec![];
+    encode_varint(&mut section, SECTION_NAME.len() as u64);
+    section.extend_from_slice(SECTION_NAME);
+    section.extend_from_slice(&payload);
+
+    let mut code = PING_WASM.to_vec();
+    code.push(0); // custom section id
+    encode_varint(&mut code, section.len() as u64);
+    code.extend_from_slice(&section);
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::InMemorySigner;
+
+    use crate::transaction::test_utils::create_test_run_opts;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_first_call_tops_up_deploy_account() {
+        let opts = create_test_run_opts();
+        let signer = InMemorySigner::from_secret_key(opts.signer_id.clone(), opts.signer_key());
+        let variant = Variant {
+            label: "first-call/pad-0".to_string(),
+            params: json!({ "step": FIRST_CALL, "padding": 0, "top_up": "1000" }),
+        };
+        let request = ContractDeploy {}
+            .get_transaction_request(&signer, opts.clone(), &variant, 1, CryptoHash::new())
+            .await
+            .unwrap();
+        let transaction = &request.signed_transaction.transaction;
+        assert_eq!(
+            transaction.receiver_id(),
+            &deploy_account_id(&opts.signer_id)
+        );
+        let [Action::FunctionCall(_), Action::Transfer(transfer)] = transaction.actions() else {
+            panic!("expected a call and a transfer");
+        };
+        assert_eq!(transfer.deposit, 1000);
+
+        let variant = Variant {
+            params: json!({ "step": FIRST_CALL, "padding": 0 }),
+            ..variant
+        };
+        let request = ContractDeploy {}
+            .get_transaction_request(&signer, opts, &variant, 1, CryptoHash::new())
+            .await
+            .unwrap();
+        assert_eq!(request.signed_transaction.transaction.actions().len(), 1);
+    }
+
+    #[test]
+    fn test_contract_code() {
+        let code = contract_code(200);
+        assert_eq!(&code[..PING_WASM.len()], PING_WASM);
+        // Section id, 2 bytes of section size, name and payload.
+        assert_eq!(code.len(), PING_WASM.len() + 1 + 2 + 1 + 8 + 16 + 200);
+        assert_eq!(
+            &code[PING_WASM.len()..PING_WASM.len() + 3],
+            &[0, 0xe1, 0x01]
+        );
+        assert_ne!(code, contract_code(200));
+    }
+}
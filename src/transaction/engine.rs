@@ -1,18 +1,26 @@
-use near_crypto::InMemorySigner;
+use futures::{stream, StreamExt};
 use near_jsonrpc_client::{methods, JsonRpcClient};
 use near_jsonrpc_primitives::types::query::QueryResponseKind;
-use near_primitives::hash::CryptoHash;
 use near_primitives::types::{BlockReference, Nonce};
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
 
-use tracing::{error, info, warn};
+use tracing::{error, field, info, info_span, instrument, warn, Instrument};
 
 use crate::{
     metrics::{Labels, Metrics},
+    slo::{SloMonitor, Webhook},
     transaction::{
+        account::{AccessKeyAddDelete, AccountCreateDelete},
+        batch::{BatchActions, BatchTransactions},
+        calls::CallDepth,
+        deploy::ContractDeploy,
         fungible_token_transfer::FungibleTokenTransfer,
-        mpc::{MpcSignEcdsa, MpcSignEddsa},
+        mpc::{MpcCkd, MpcSignEcdsa, MpcSignEddsa},
+        nft::{NftMint, NftTransfer},
+        relayed::RelayedTokenTransfer,
+        signer,
         swap::Swap,
         token_transfer_default::TokenTransferDefault,
         token_transfer_final::TokenTransferFinal,
@@ -21,10 +29,17 @@ use crate::{
     TransactionSample,
 };
 
-use super::TransactionKind;
+use super::block_tracker::BlockTracker;
+use super::nonces::Nonces;
+use super::{
+    ExpiredTransactionError, InvalidNonceError, InvalidResultError, TransactionKind, Variant,
+};
 use crate::config::Opts;
+use crate::credentials::load_keys;
 use tokio::{sync::oneshot::Receiver, task::JoinSet, time::interval};
 
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
 type Transactions = HashMap<TransactionKind, Arc<dyn TransactionSample>>;
 
 #[derive(Default)]
@@ -39,7 +54,10 @@ impl Engine {
 
         macro_rules! add_transaction {
             ($name: ident) => {
-                let tx = Arc::new($name {});
+                add_transaction!($name {});
+            };
+            ($tx: expr) => {
+                let tx = Arc::new($tx);
                 transactions.insert(tx.kind(), tx as Arc<dyn TransactionSample>);
             };
         }
@@ -51,8 +69,16 @@ impl Engine {
         add_transaction!(Swap);
         add_transaction!(MpcSignEcdsa);
         add_transaction!(MpcSignEddsa);
-        // This is not yet functional, so failures are expected for these transactions
-        // add_transaction!(MpcCkd);
+        add_transaction!(MpcCkd);
+        add_transaction!(NftMint);
+        add_transaction!(NftTransfer);
+        add_transaction!(AccountCreateDelete::default());
+        add_transaction!(AccessKeyAddDelete::default());
+        add_transaction!(ContractDeploy);
+        add_transaction!(CallDepth);
+        add_transaction!(RelayedTokenTransfer::default());
+        add_transaction!(BatchActions);
+        add_transaction!(BatchTransactions);
 
         Engine { transactions }
     }
@@ -73,13 +99,25 @@ impl Engine {
     /// Runs the engine until the program is stopped.
     pub async fn run(
         &self,
-        opts: Opts,
+        mut opts: Opts,
         metrics: Arc<Metrics>,
         stop_signal: Receiver<()>,
     ) -> anyhow::Result<()> {
         info!("starting transaction engine");
+        // Connections are kept alive and reused by all runs.
+        let rpc_client = create_rpc_client(&opts)?;
+        let network = resolve_network(&opts, &rpc_client).await;
+        info!("benchmarking network {}", network);
+        load_keys(&mut opts, &network)?;
+        opts.network = Some(network);
+        let blocks = Arc::new(BlockTracker::new(
+            rpc_client.clone(),
+            opts.block_poll_interval,
+            opts.block_max_age,
+        ));
         tokio::select! {
-            res = self.run_impl(opts, metrics) => res,
+            res = self.run_impl(opts.clone(), metrics, rpc_client, blocks.clone()) => res,
+            _ = blocks.run() => Ok(()),
             _ = stop_signal => {
                 info!("transaction engine shutting down");
                 Ok(())
@@ -87,14 +125,42 @@ impl Engine {
         }
     }
 
-    async fn run_impl(&self, opts: Opts, metrics: Arc<Metrics>) -> anyhow::Result<()> {
+    async fn run_impl(
+        &self,
+        opts: Opts,
+        metrics: Arc<Metrics>,
+        rpc_client: JsonRpcClient,
+        blocks: Arc<BlockTracker>,
+    ) -> anyhow::Result<()> {
+        clean_up(&self.transactions, &opts, &rpc_client, &blocks).await;
+        // Shared by all runs, including the concurrent runs of interval groups.
+        let nonces = Arc::new(Nonces::default());
+        let slo = Arc::new(SloMonitor::new(
+            opts.slo.clone().unwrap_or_default(),
+            opts.slo_window,
+            opts.slo_min_samples,
+            opts.slo_webhook_url.clone().map(|url| Webhook {
+                url: url.0,
+                format: opts.slo_webhook_format,
+                routing_key: opts.slo_webhook_routing_key.clone().map(|key| key.0),
+            }),
+        ));
         // If interval_overwrite is specified, run transactions with different intervals
         if let Some(interval_overwrite) = opts.interval_overwrite.clone() {
-            self.run_with_custom_intervals(opts, metrics, &interval_overwrite)
-                .await
+            self.run_with_custom_intervals(
+                opts,
+                metrics,
+                slo,
+                rpc_client,
+                blocks,
+                nonces,
+                &interval_overwrite,
+            )
+            .await
         } else {
             // Default behavior: run all transactions at the same interval
-            self.run_with_default_interval(opts, metrics).await
+            self.run_with_default_interval(opts, metrics, slo, rpc_client, blocks, nonces)
+                .await
         }
     }
 
@@ -102,18 +168,28 @@ impl Engine {
         &self,
         opts: Opts,
         metrics: Arc<Metrics>,
+        slo: Arc<SloMonitor>,
+        rpc_client: JsonRpcClient,
+        blocks: Arc<BlockTracker>,
+        nonces: Arc<Nonces>,
     ) -> anyhow::Result<()> {
         let mut interval = interval(opts.period);
         loop {
             interval.tick().await;
-            self.run_all_once(opts.clone(), &metrics).await;
+            self.run_all_once(opts.clone(), &metrics, &slo, &rpc_client, &blocks, &nonces)
+                .await;
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn run_with_custom_intervals(
         &self,
         opts: Opts,
         metrics: Arc<Metrics>,
+        slo: Arc<SloMonitor>,
+        rpc_client: JsonRpcClient,
+        blocks: Arc<BlockTracker>,
+        nonces: Arc<Nonces>,
         interval_overwrite: &HashMap<TransactionKind, std::time::Duration>,
     ) -> anyhow::Result<()> {
         let mut tasks = JoinSet::new();
@@ -147,15 +223,16 @@ impl Engine {
                 .or_default()
                 .extend(default_transactions);
         }
-        let run_account_transactions_once_mutex = Arc::new(Mutex::new(()));
-
-        // Spawn a task for each interval group
+        // Spawn a task for each interval group. Groups run concurrently, their transactions
+        // share the nonces of the signer key.
         for (interval_duration, transaction_kinds) in interval_groups.into_iter() {
             let opts_clone = opts.clone();
             let metrics_clone = metrics.clone();
+            let slo_clone = slo.clone();
+            let rpc_client_clone = rpc_client.clone();
+            let blocks_clone = blocks.clone();
             let transactions_clone = transactions.clone();
-
-            let mutex_clone = Arc::clone(&run_account_transactions_once_mutex);
+            let nonces_clone = nonces.clone();
             tasks.spawn(async move {
                 let mut interval = interval(interval_duration);
                 loop {
@@ -175,12 +252,14 @@ impl Engine {
                         .map(|(kind, tx)| (kind.clone(), tx.clone()))
                         .collect();
 
-                    let _lock = mutex_clone.lock().await;
-
                     run_account_transactions_once(
                         filtered_transactions,
                         opts_clone.clone(),
                         metrics_clone.clone(),
+                        slo_clone.clone(),
+                        rpc_client_clone.clone(),
+                        blocks_clone.clone(),
+                        nonces_clone.clone(),
                     )
                     .await;
                 }
@@ -197,13 +276,34 @@ impl Engine {
         Ok(())
     }
 
-    async fn run_all_once(&self, opts: Opts, metrics: &Arc<Metrics>) {
+    async fn run_all_once(
+        &self,
+        opts: Opts,
+        metrics: &Arc<Metrics>,
+        slo: &Arc<SloMonitor>,
+        rpc_client: &JsonRpcClient,
+        blocks: &Arc<BlockTracker>,
+        nonces: &Arc<Nonces>,
+    ) {
         info!("running selected transactions: {:?}", opts.transaction_kind);
         let mut tasks = JoinSet::new();
         let metrics = metrics.clone();
+        let slo = slo.clone();
+        let rpc_client = rpc_client.clone();
+        let blocks = blocks.clone();
+        let nonces = nonces.clone();
         let transactions = self.transactions.clone();
         tasks.spawn(async move {
-            run_account_transactions_once(transactions, opts, metrics).await;
+            run_account_transactions_once(
+                transactions,
+                opts,
+                metrics,
+                slo,
+                rpc_client,
+                blocks,
+                nonces,
+            )
+            .await;
         });
         while let Some(join_result) = tasks.join_next().await {
             if let Err(err) = join_result {
@@ -213,102 +313,343 @@ impl Engine {
     }
 }
 
+/// Whether the transactions of `kind` are executed, explicitly selected or enabled by default.
+fn is_selected(opts: &Opts, kind: &TransactionKind, tx_sample: &dyn TransactionSample) -> bool {
+    if opts.transaction_kind.is_empty() {
+        tx_sample.enabled_by_default()
+    } else {
+        opts.transaction_kind.contains(kind)
+    }
+}
+
+/// Removes what previous runs of the selected transactions left on chain. Failures are only
+/// warned about, the leftovers are retried at the next start.
+#[instrument(name = "clean_up", skip_all)]
+async fn clean_up(
+    transactions: &Transactions,
+    opts: &Opts,
+    rpc_client: &JsonRpcClient,
+    blocks: &BlockTracker,
+) {
+    for (kind, tx_sample) in transactions {
+        if !is_selected(opts, kind, tx_sample.as_ref()) {
+            continue;
+        }
+        let result = match blocks.block_hash().await {
+            Ok(block_hash) => tx_sample.clean_up(rpc_client, opts, block_hash).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            warn!(
+                "failed to clean up after previous runs of {}: {}",
+                kind, err
+            );
+        }
+    }
+}
+
+#[instrument(name = "benchmark_run", skip_all, fields(signer = %opts.signer_id))]
 async fn run_account_transactions_once(
     transactions: Transactions,
     opts: Opts,
     metrics: Arc<Metrics>,
+    slo: Arc<SloMonitor>,
+    rpc_client: JsonRpcClient,
+    blocks: Arc<BlockTracker>,
+    nonces: Arc<Nonces>,
 ) {
-    let network = if opts.rpc_url.contains("mainnet") {
-        "mainnet"
-    } else if opts.rpc_url.contains("testnet") {
-        "testnet"
-    } else if opts.rpc_url.contains("statelessnet") {
-        "statelessnet"
-    } else {
-        "localnet"
-    };
-
-    let rpc_client = JsonRpcClient::connect(&opts.rpc_url);
-    let (nonce, block_hash) = match get_nonce_and_block_hash(&opts, &rpc_client).await {
-        Ok(res) => res,
+    let network = opts.network.clone().unwrap_or_default();
+    match get_nonce(&opts, &rpc_client).await {
+        // Skips the nonces used by other clients of the key since the last run.
+        Ok(nonce) => nonces.observe(nonce).await,
         Err(err) => {
             error!("Error: {}", err);
             return;
         }
     };
 
-    for (tx_number, (kind, tx_sample)) in transactions.iter().enumerate() {
-        if !opts.transaction_kind.is_empty() && !opts.transaction_kind.contains(kind) {
-            continue;
-        }
-        let labels = Labels::new(kind.to_string(), network.to_string(), opts.location.clone());
-        metrics.attempted_transactions.get_or_create(&labels).inc();
-        for repeats_number in 0..opts.repeats_number {
-            let tx_sample = tx_sample.clone();
+    // Collected first, a stream mapping the iterator lazily would not be `Send` in spawned runs.
+    let runs: Vec<_> = transactions
+        .iter()
+        .filter(|(kind, tx_sample)| is_selected(&opts, kind, tx_sample.as_ref()))
+        .map(|(kind, tx_sample)| {
+            run_kind_once(
+                kind,
+                tx_sample.as_ref(),
+                &network,
+                &opts,
+                &metrics,
+                &slo,
+                &rpc_client,
+                &blocks,
+                &nonces,
+            )
+        })
+        .collect();
+    stream::iter(runs)
+        .buffer_unordered(opts.kind_parallelism.max(1))
+        .collect::<()>()
+        .await;
+}
+
+/// Executes every variant of `tx_sample`, one after the other, `repeats_number` times. Each
+/// repeat runs all the variants in order, as a variant may depend on the previous one, e.g. the
+/// first call of a contract on its deployment.
+#[allow(clippy::too_many_arguments)]
+async fn run_kind_once(
+    kind: &TransactionKind,
+    tx_sample: &dyn TransactionSample,
+    network: &str,
+    opts: &Opts,
+    metrics: &Arc<Metrics>,
+    slo: &SloMonitor,
+    rpc_client: &JsonRpcClient,
+    blocks: &BlockTracker,
+    nonces: &Nonces,
+) {
+    let variants: Vec<(Variant, Labels)> = tx_sample
+        .variants(opts)
+        .into_iter()
+        .map(|variant| {
+            let labels = Labels::new(
+                kind.to_string(),
+                variant.label.clone(),
+                network.to_string(),
+                opts.location.clone(),
+            );
+            metrics.attempted_transactions.get_or_create(&labels).inc();
+            (variant, labels)
+        })
+        .collect();
+    let interval = opts
+        .interval_overwrite
+        .as_ref()
+        .and_then(|intervals| intervals.get(kind))
+        .copied()
+        .unwrap_or(opts.period);
+    let executions_per_day =
+        DAY.as_secs_f64() / interval.as_secs_f64() * opts.repeats_number as f64;
+    for repeats_number in 0..opts.repeats_number {
+        for (variant, labels) in &variants {
             info!(
-                "executing transaction {}#{} for {}",
+                "executing transaction {}{}#{} for {}",
                 tx_sample.kind(),
+                variant,
                 repeats_number,
                 opts.signer_id
             );
 
-            let current_nonce =
-                nonce.saturating_add((tx_number * opts.repeats_number + repeats_number + 1) as u64);
-            match tx_sample
-                .execute(
-                    &rpc_client,
-                    opts.clone(),
-                    &metrics,
-                    &labels,
-                    current_nonce,
-                    block_hash,
+            metrics
+                .last_attempt_timestamp
+                .get_or_create(labels)
+                .set(unix_timestamp());
+            let in_flight = metrics.in_flight_transactions.get_or_create(labels).clone();
+            in_flight.inc();
+            let tokens_burnt = metrics.tokens_burnt.get_or_create(labels).clone();
+            let tokens_burnt_before = tokens_burnt.get();
+            let span = info_span!(
+                "sample",
+                kind = %kind,
+                variant = %variant.label,
+                rpc = %opts.rpc_url,
+                repeat = repeats_number,
+                tx_hash = field::Empty,
+                outcome = field::Empty,
+                otel.status_code = field::Empty,
+            );
+            let mut result = execute_with_recent_block(
+                tx_sample, rpc_client, opts, variant, metrics, labels, nonces, blocks,
+            )
+            .instrument(span.clone())
+            .await;
+            let retry = match &result {
+                Err(err) if err.is::<ExpiredTransactionError>() => {
+                    blocks.invalidate();
+                    Some("expired, signing it again with a recent block")
+                }
+                Err(err) if err.is::<InvalidNonceError>() => {
+                    Some("used a nonce already used, signing it again with a fresh one")
+                }
+                _ => None,
+            };
+            if let Some(reason) = retry {
+                warn!(
+                    "transaction {}{}#{} {}",
+                    tx_sample.kind(),
+                    variant,
+                    repeats_number,
+                    reason
+                );
+                result = execute_with_recent_block(
+                    tx_sample, rpc_client, opts, variant, metrics, labels, nonces, blocks,
                 )
-                .await
-            {
+                .instrument(span.clone())
+                .await;
+            }
+            in_flight.dec();
+            // Failed transactions burn tokens too, so the estimate is updated in both cases.
+            let cost = tokens_burnt.get() - tokens_burnt_before;
+            if cost > 0.0 {
+                metrics
+                    .estimated_daily_spend
+                    .get_or_create(labels)
+                    .set(cost * executions_per_day);
+            }
+            span.record(
+                "outcome",
+                if result.is_ok() { "success" } else { "failure" },
+            );
+            span.record(
+                "otel.status_code",
+                if result.is_ok() { "OK" } else { "ERROR" },
+            );
+            match result {
                 Ok(outcome) => {
                     info!(
-                        "completed transaction {}#{} for {}: {:?}",
+                        "completed transaction {}{}#{} for {}: {:?}",
                         tx_sample.kind(),
+                        variant,
                         repeats_number,
                         opts.signer_id,
                         outcome
                     );
-                    metrics.successful_transactions.get_or_create(&labels).inc();
+                    metrics.successful_transactions.get_or_create(labels).inc();
                     metrics
                         .transaction_latency
-                        .get_or_create(&labels)
+                        .get_or_create(labels)
                         .observe(outcome.as_secs_f64());
+                    metrics
+                        .last_latency
+                        .get_or_create(labels)
+                        .set(outcome.as_secs_f64());
+                    metrics
+                        .last_success_timestamp
+                        .get_or_create(labels)
+                        .set(unix_timestamp());
+                    slo.record(kind, labels, metrics, Some(outcome));
                 }
                 Err(err) => {
                     warn!(
-                        "error during transaction {}#{} for {}: {}",
+                        "error during transaction {}{}#{} for {}: {}",
                         tx_sample.kind(),
+                        variant,
                         repeats_number,
                         opts.signer_id,
                         err
                     );
-                    metrics.failed_transactions.get_or_create(&labels).inc();
+                    metrics.failed_transactions.get_or_create(labels).inc();
+                    if err.is::<InvalidResultError>() {
+                        metrics.invalid_results.get_or_create(labels).inc();
+                    }
+                    slo.record(kind, labels, metrics, None);
                 }
             }
         }
     }
 }
 
-async fn get_nonce_and_block_hash(
-    opts: &Opts,
-    rpc_client: &JsonRpcClient,
-) -> anyhow::Result<(Nonce, CryptoHash)> {
-    if opts.rpc_url.contains("fake") {
-        return Ok((0, CryptoHash::new()));
+/// Network label used when no network is configured and the RPC does not report its chain id.
+const UNKNOWN_NETWORK: &str = "unknown";
+
+/// Returns the configured network, warning if it differs from the chain id reported by the RPC,
+/// or the chain id if no network is configured. An unreachable RPC is only warned about, so
+/// that the benchmark starts and reports the outage as failed transactions.
+async fn resolve_network(opts: &Opts, rpc_client: &JsonRpcClient) -> String {
+    let chain_id = rpc_client
+        .call(methods::status::RpcStatusRequest)
+        .await
+        .map(|status| status.chain_id);
+    match (&opts.network, chain_id) {
+        (Some(network), Ok(chain_id)) => {
+            if *network != chain_id {
+                warn!(
+                    "configured network {} does not match chain id {} of {}",
+                    network, chain_id, opts.rpc_url
+                );
+            }
+            network.clone()
+        }
+        (Some(network), Err(err)) => {
+            warn!(
+                "failed to check network {} against the RPC: {}",
+                network, err
+            );
+            network.clone()
+        }
+        (None, Ok(chain_id)) => chain_id,
+        (None, Err(err)) => {
+            warn!(
+                "failed to detect the network from {}, using {}, set it with --network: {}",
+                opts.rpc_url, UNKNOWN_NETWORK, err
+            );
+            UNKNOWN_NETWORK.to_string()
+        }
+    }
+}
+
+/// Creates the RPC client with the timeouts, headers and proxy of `opts`.
+fn create_rpc_client(opts: &Opts) -> anyhow::Result<JsonRpcClient> {
+    let mut headers = opts.rpc_headers.clone().unwrap_or_default();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Some(api_key) = &opts.rpc_api_key {
+        let mut api_key = HeaderValue::from_str(&api_key.0)?;
+        api_key.set_sensitive(true);
+        headers.insert("x-api-key", api_key);
     }
-    let signer = InMemorySigner::from_secret_key(opts.signer_id.clone(), opts.signer_key.clone());
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .timeout(opts.rpc_timeout)
+        .connect_timeout(opts.rpc_connect_timeout);
+    if let Some(proxy) = &opts.rpc_proxy {
+        builder = builder.proxy(reqwest::Proxy::all(&proxy.0)?);
+    }
+    Ok(JsonRpcClient::with(builder.build()?).connect(&opts.rpc_url))
+}
+
+/// Executes a transaction referencing the block tracked by `blocks`.
+#[allow(clippy::too_many_arguments)]
+async fn execute_with_recent_block(
+    tx_sample: &dyn TransactionSample,
+    rpc_client: &JsonRpcClient,
+    opts: &Opts,
+    variant: &Variant,
+    metrics: &Arc<Metrics>,
+    labels: &Labels,
+    nonces: &Nonces,
+    blocks: &BlockTracker,
+) -> anyhow::Result<Duration> {
+    let block_hash = blocks.block_hash().await?;
+    tx_sample
+        .execute(
+            rpc_client,
+            opts.clone(),
+            variant,
+            metrics,
+            labels,
+            nonces,
+            block_hash,
+        )
+        .await
+}
+
+/// Returns the current unix time in seconds, as exported by the timestamp gauges.
+fn unix_timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[instrument(name = "fetch_nonce", skip_all)]
+async fn get_nonce(opts: &Opts, rpc_client: &JsonRpcClient) -> anyhow::Result<Nonce> {
+    let signer = signer::signer(opts);
 
     let access_key_response = rpc_client
         .call(methods::query::RpcQueryRequest {
             block_reference: BlockReference::latest(),
             request: near_primitives::views::QueryRequest::ViewAccessKey {
-                account_id: signer.account_id.clone(),
-                public_key: signer.public_key.clone(),
+                account_id: signer.account_id().clone(),
+                public_key: signer.public_key(),
             },
         })
         .await?;
@@ -317,31 +658,40 @@ async fn get_nonce_and_block_hash(
         QueryResponseKind::AccessKey(access_key) => access_key.nonce,
         _ => anyhow::bail!(
             "Unreachable code: could not retrieve access key for {}",
-            signer.account_id
+            signer.account_id()
         ),
     };
-    Ok((nonce, access_key_response.block_hash))
+    Ok(nonce)
 }
 
 #[cfg(test)]
 mod tests {
     use std::{
-        net::SocketAddr,
-        str::FromStr,
         sync::atomic::{AtomicU64, Ordering},
         time::Duration,
     };
 
     use async_trait::async_trait;
+    use axum::{http::HeaderMap, routing::post, Json, Router};
     use more_asserts::assert_ge;
-    use near_crypto::{InMemorySigner, KeyType, SecretKey};
     use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
     use near_primitives::hash::CryptoHash;
     use near_primitives::types::Nonce;
-    use tokio::{sync::oneshot, time::sleep};
+    use reqwest::header::AUTHORIZATION;
+    use serde_json::json;
+
+    use crate::config::Secret;
+    use tokio::net::TcpListener;
+    use tokio::{
+        sync::{mpsc, oneshot},
+        time::sleep,
+    };
 
-    use crate::config::Mode;
     use crate::metrics::{create_registry_and_metrics, Labels};
+    use crate::transaction::signer::Signer;
+
+    use crate::transaction::test_utils::{create_test_block, create_test_run_opts, start_test_rpc};
+    use crate::transaction::Variant;
 
     use super::*;
 
@@ -364,13 +714,14 @@ mod tests {
             unimplemented!();
         }
 
-        fn get_transaction_request(
+        async fn get_transaction_request(
             &self,
-            _: InMemorySigner,
+            _: &dyn Signer,
             _: Opts,
+            _: &Variant,
             _: Nonce,
             _: CryptoHash,
-        ) -> RpcSendTransactionRequest {
+        ) -> anyhow::Result<RpcSendTransactionRequest> {
             unimplemented!();
         }
 
@@ -378,9 +729,10 @@ mod tests {
             &self,
             _rpc_client: &JsonRpcClient,
             _opts: Opts,
+            _variant: &Variant,
             _metrics: &Arc<Metrics>,
             _labels: &Labels,
-            _nonce: Nonce,
+            _nonces: &Nonces,
             _block_hash: CryptoHash,
         ) -> anyhow::Result<Duration> {
             self.exec_counter
@@ -404,13 +756,14 @@ mod tests {
             unimplemented!();
         }
 
-        fn get_transaction_request(
+        async fn get_transaction_request(
             &self,
-            _: InMemorySigner,
+            _: &dyn Signer,
             _: Opts,
+            _: &Variant,
             _: Nonce,
             _: CryptoHash,
-        ) -> RpcSendTransactionRequest {
+        ) -> anyhow::Result<RpcSendTransactionRequest> {
             unimplemented!();
         }
 
@@ -418,9 +771,10 @@ mod tests {
             &self,
             _rpc_client: &JsonRpcClient,
             _opts: Opts,
+            _variant: &Variant,
             _metrics: &Arc<Metrics>,
             _labels: &Labels,
-            _nonce: Nonce,
+            _nonces: &Nonces,
             _block_hash: CryptoHash,
         ) -> anyhow::Result<Duration> {
             self.exec_counter.fetch_add(1, Ordering::SeqCst);
@@ -428,25 +782,614 @@ mod tests {
         }
     }
 
-    fn create_test_run_opts() -> Opts {
-        Opts {
-            mode: Mode::Run,
-            rpc_url: "https://rpc.fake.near.org".to_string(),
-            signer_id: "cat.near".parse().unwrap(),
-            signer_key: SecretKey::from_random(KeyType::ED25519),
-            receiver_id: "dog.near".parse().unwrap(),
-            mpc_contract_id: "frog.near".parse().unwrap(),
-            wrap_near_id: "frog.near".parse().unwrap(),
-            ft_account_id: "bear.near".parse().unwrap(),
-            exchange_id: "flamingo.near".parse().unwrap(),
-            pool_id: 0,
-            transaction_kind: vec![],
-            period: Duration::from_millis(1),
-            interval_overwrite: None,
-            metric_server_address: SocketAddr::from_str("0.0.0.0:9000").unwrap(),
-            location: LOCATION.to_string(),
-            repeats_number: 1,
+    /// Fails with an expired transaction at the first execution only.
+    #[derive(Default)]
+    struct TestExpiredTransaction {
+        exec_counter: AtomicU64,
+    }
+
+    #[async_trait]
+    impl TransactionSample for TestExpiredTransaction {
+        fn kind(&self) -> TransactionKind {
+            TransactionKind::TokenTransferFinal
+        }
+
+        fn get_name(&self) -> &str {
+            "expired"
+        }
+
+        async fn get_transaction_request(
+            &self,
+            _: &dyn Signer,
+            _: Opts,
+            _: &Variant,
+            _: Nonce,
+            _: CryptoHash,
+        ) -> anyhow::Result<RpcSendTransactionRequest> {
+            unimplemented!();
+        }
+
+        async fn execute(
+            &self,
+            _rpc_client: &JsonRpcClient,
+            _opts: Opts,
+            _variant: &Variant,
+            _metrics: &Arc<Metrics>,
+            _labels: &Labels,
+            _nonces: &Nonces,
+            _block_hash: CryptoHash,
+        ) -> anyhow::Result<Duration> {
+            if self.exec_counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(ExpiredTransactionError("expired".to_string()).into());
+            }
+            Ok(Duration::from_millis(1))
+        }
+    }
+
+    /// Records the nonces it is executed with and the executions running at the same time.
+    struct TestConcurrentTransaction {
+        kind: TransactionKind,
+        running: Arc<AtomicU64>,
+        max_running: Arc<AtomicU64>,
+        nonces: std::sync::Mutex<Vec<Nonce>>,
+    }
+
+    #[async_trait]
+    impl TransactionSample for TestConcurrentTransaction {
+        fn kind(&self) -> TransactionKind {
+            self.kind.clone()
+        }
+
+        fn get_name(&self) -> &str {
+            "concurrent"
+        }
+
+        async fn get_transaction_request(
+            &self,
+            _: &dyn Signer,
+            _: Opts,
+            _: &Variant,
+            _: Nonce,
+            _: CryptoHash,
+        ) -> anyhow::Result<RpcSendTransactionRequest> {
+            unimplemented!();
+        }
+
+        async fn execute(
+            &self,
+            _rpc_client: &JsonRpcClient,
+            _opts: Opts,
+            _variant: &Variant,
+            _metrics: &Arc<Metrics>,
+            _labels: &Labels,
+            nonces: &Nonces,
+            _block_hash: CryptoHash,
+        ) -> anyhow::Result<Duration> {
+            let nonce = nonces.reserve().await.0;
+            self.nonces.lock().unwrap().push(nonce);
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            sleep(Duration::from_millis(50)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(Duration::from_millis(50))
+        }
+    }
+
+    /// NEAR transfer sent by the default `execute`, whose preparation takes `prepare_delay`.
+    struct TestPreparedTransaction {
+        kind: TransactionKind,
+        prepare_delay: Duration,
+    }
+
+    #[async_trait]
+    impl TransactionSample for TestPreparedTransaction {
+        fn kind(&self) -> TransactionKind {
+            self.kind.clone()
+        }
+
+        fn get_name(&self) -> &str {
+            "prepared"
+        }
+
+        async fn prepare(
+            &self,
+            _rpc_client: &JsonRpcClient,
+            _opts: &Opts,
+            variant: &Variant,
+        ) -> anyhow::Result<Variant> {
+            sleep(self.prepare_delay).await;
+            Ok(variant.clone())
+        }
+
+        async fn get_transaction_request(
+            &self,
+            signer: &dyn Signer,
+            opts: Opts,
+            variant: &Variant,
+            nonce: Nonce,
+            block_hash: CryptoHash,
+        ) -> anyhow::Result<RpcSendTransactionRequest> {
+            TokenTransferDefault {}
+                .get_transaction_request(signer, opts, variant, nonce, block_hash)
+                .await
+        }
+    }
+
+    /// Transaction with two variants, recording the labels of the executed variants.
+    #[derive(Default)]
+    struct TestVariantsTransaction {
+        executed: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl TransactionSample for TestVariantsTransaction {
+        fn kind(&self) -> TransactionKind {
+            TransactionKind::ContractDeploy
+        }
+
+        fn get_name(&self) -> &str {
+            "variants"
         }
+
+        fn variants(&self, _opts: &Opts) -> Vec<Variant> {
+            ["deploy", "first-call"]
+                .map(|label| Variant {
+                    label: label.to_string(),
+                    params: json!({}),
+                })
+                .to_vec()
+        }
+
+        async fn get_transaction_request(
+            &self,
+            _: &dyn Signer,
+            _: Opts,
+            _: &Variant,
+            _: Nonce,
+            _: CryptoHash,
+        ) -> anyhow::Result<RpcSendTransactionRequest> {
+            unimplemented!();
+        }
+
+        async fn execute(
+            &self,
+            _rpc_client: &JsonRpcClient,
+            _opts: Opts,
+            variant: &Variant,
+            _metrics: &Arc<Metrics>,
+            _labels: &Labels,
+            _nonces: &Nonces,
+            _block_hash: CryptoHash,
+        ) -> anyhow::Result<Duration> {
+            self.executed.lock().unwrap().push(variant.label.clone());
+            Ok(Duration::from_millis(1))
+        }
+    }
+
+    /// Returns a tracker of a block that does not expire during a test, so no block is fetched.
+    fn create_test_block_tracker() -> Arc<BlockTracker> {
+        let blocks = BlockTracker::new(
+            JsonRpcClient::connect("http://127.0.0.1:9"),
+            Duration::from_secs(10),
+            Duration::from_secs(60),
+        );
+        blocks.update(CryptoHash::new(), 1);
+        Arc::new(blocks)
+    }
+
+    /// Returns the result of querying an access key with `nonce`.
+    fn create_test_access_key(nonce: Nonce) -> serde_json::Value {
+        json!({
+            "nonce": nonce,
+            "permission": "FullAccess",
+            "block_height": 1,
+            "block_hash": CryptoHash::new().to_string(),
+        })
+    }
+
+    /// Starts an RPC answering the nonce of the signer key, which is 0, and a new block at every
+    /// request, returning its URL. Other requests fail.
+    async fn start_chain_rpc() -> String {
+        let height = Arc::new(AtomicU64::new(0));
+        start_test_rpc(move |method, params| match method {
+            "query" if params["request_type"] == "view_access_key" => Ok(create_test_access_key(0)),
+            "block" => {
+                let height = height.fetch_add(1, Ordering::SeqCst) + 1;
+                let hash = CryptoHash::hash_bytes(&height.to_le_bytes());
+                Ok(serde_json::to_value(create_test_block(height, hash)).unwrap())
+            }
+            _ => Err(json!({"code": -32000, "message": "Server error", "data": "unsupported"})),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_run_once_updates_gauges() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let mut engine = Engine::default();
+        engine.add_transaction(Arc::new(TestOkTransaction::default()));
+        engine.add_transaction(Arc::new(TestErrTransaction::default()));
+        run_account_transactions_once(
+            engine.transactions().clone(),
+            create_test_run_opts(),
+            metrics.clone(),
+            Arc::new(SloMonitor::new(
+                HashMap::new(),
+                Duration::from_secs(1),
+                1,
+                None,
+            )),
+            JsonRpcClient::connect(start_chain_rpc().await),
+            create_test_block_tracker(),
+            Arc::new(Nonces::default()),
+        )
+        .await;
+
+        let ok_labels = Labels::new(
+            TransactionKind::TokenTransferDefault.to_string(),
+            String::new(),
+            NETWORK.to_string(),
+            LOCATION.to_string(),
+        );
+        assert!(
+            metrics
+                .last_attempt_timestamp
+                .get_or_create(&ok_labels)
+                .get()
+                > 0.0
+        );
+        assert!(
+            metrics
+                .last_success_timestamp
+                .get_or_create(&ok_labels)
+                .get()
+                > 0.0
+        );
+        assert_eq!(
+            metrics.last_latency.get_or_create(&ok_labels).get(),
+            Duration::from_millis(1).as_secs_f64()
+        );
+        assert_eq!(
+            metrics
+                .in_flight_transactions
+                .get_or_create(&ok_labels)
+                .get(),
+            0
+        );
+
+        let err_labels = Labels::new(
+            TransactionKind::FungibleTokenTransfer.to_string(),
+            String::new(),
+            NETWORK.to_string(),
+            LOCATION.to_string(),
+        );
+        assert!(
+            metrics
+                .last_attempt_timestamp
+                .get_or_create(&err_labels)
+                .get()
+                > 0.0
+        );
+        assert_eq!(
+            metrics
+                .last_success_timestamp
+                .get_or_create(&err_labels)
+                .get(),
+            0.0
+        );
+        assert_eq!(
+            metrics
+                .in_flight_transactions
+                .get_or_create(&err_labels)
+                .get(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_once_retries_expired_transaction() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let tx = Arc::new(TestExpiredTransaction::default());
+        let mut engine = Engine::default();
+        engine.add_transaction(tx.clone());
+        let rpc_client = JsonRpcClient::connect(start_chain_rpc().await);
+        // The expired block is forgotten, the retry references a block fetched from the RPC.
+        let blocks = Arc::new(BlockTracker::new(
+            rpc_client.clone(),
+            Duration::from_secs(10),
+            Duration::from_secs(60),
+        ));
+        run_account_transactions_once(
+            engine.transactions().clone(),
+            create_test_run_opts(),
+            metrics.clone(),
+            Arc::new(SloMonitor::new(
+                HashMap::new(),
+                Duration::from_secs(1),
+                1,
+                None,
+            )),
+            rpc_client,
+            blocks,
+            Arc::new(Nonces::default()),
+        )
+        .await;
+
+        let labels = Labels::new(
+            tx.kind().to_string(),
+            String::new(),
+            NETWORK.to_string(),
+            LOCATION.to_string(),
+        );
+        assert_eq!(tx.exec_counter.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            metrics.successful_transactions.get_or_create(&labels).get(),
+            1
+        );
+        assert_eq!(metrics.failed_transactions.get_or_create(&labels).get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_run_once_executes_kinds_concurrently() {
+        for (kind_parallelism, expected_max_running) in [(1, 1), (2, 2)] {
+            let (_registry, metrics) = create_registry_and_metrics();
+            let running = Arc::new(AtomicU64::new(0));
+            let max_running = Arc::new(AtomicU64::new(0));
+            let txs: Vec<_> = [
+                TransactionKind::TokenTransferDefault,
+                TransactionKind::TokenTransferFinal,
+                TransactionKind::FungibleTokenTransfer,
+            ]
+            .into_iter()
+            .map(|kind| {
+                Arc::new(TestConcurrentTransaction {
+                    kind,
+                    running: running.clone(),
+                    max_running: max_running.clone(),
+                    nonces: Default::default(),
+                })
+            })
+            .collect();
+            let mut engine = Engine::default();
+            for tx in &txs {
+                engine.add_transaction(tx.clone());
+            }
+            run_account_transactions_once(
+                engine.transactions().clone(),
+                Opts {
+                    transaction_kind: txs.iter().map(|tx| tx.kind()).collect(),
+                    repeats_number: 2,
+                    kind_parallelism,
+                    ..create_test_run_opts()
+                },
+                metrics,
+                Arc::new(SloMonitor::new(
+                    HashMap::new(),
+                    Duration::from_secs(1),
+                    1,
+                    None,
+                )),
+                JsonRpcClient::connect(start_chain_rpc().await),
+                create_test_block_tracker(),
+                Arc::new(Nonces::default()),
+            )
+            .await;
+
+            assert_eq!(max_running.load(Ordering::SeqCst), expected_max_running);
+            // Every execution reserved its own nonce.
+            let mut nonces: Vec<Nonce> = txs
+                .iter()
+                .flat_map(|tx| tx.nonces.lock().unwrap().clone())
+                .collect();
+            nonces.sort();
+            assert_eq!(nonces, (0..6).collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_kind_once_repeats_all_variants_in_order() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let tx = TestVariantsTransaction::default();
+        let opts = Opts {
+            repeats_number: 2,
+            ..create_test_run_opts()
+        };
+        run_kind_once(
+            &tx.kind(),
+            &tx,
+            NETWORK,
+            &opts,
+            &metrics,
+            &SloMonitor::new(HashMap::new(), Duration::from_secs(1), 1, None),
+            &JsonRpcClient::connect("http://127.0.0.1:9"),
+            &create_test_block_tracker(),
+            &Nonces::default(),
+        )
+        .await;
+
+        // Every first call follows the deployment of its own repeat.
+        assert_eq!(
+            *tx.executed.lock().unwrap(),
+            ["deploy", "first-call", "deploy", "first-call"]
+        );
+        let labels = Labels::new(
+            tx.kind().to_string(),
+            "deploy".to_string(),
+            NETWORK.to_string(),
+            LOCATION.to_string(),
+        );
+        assert_eq!(
+            metrics.successful_transactions.get_or_create(&labels).get(),
+            2
+        );
+    }
+
+    /// Starts an RPC rejecting all transactions, the first one with an invalid nonce if
+    /// `ak_nonce` is set, returning its URL and the nonces of the transactions in the order they
+    /// are received.
+    async fn start_send_tx_rpc(
+        ak_nonce: Option<Nonce>,
+    ) -> (String, mpsc::UnboundedReceiver<Nonce>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let received = Arc::new(AtomicU64::new(0));
+        let app = Router::new().route(
+            "/",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                if body["method"] == "query" {
+                    let result = create_test_access_key(0);
+                    return Json(json!({"jsonrpc": "2.0", "id": body["id"], "result": result}));
+                }
+                let request: RpcSendTransactionRequest =
+                    serde_json::from_value(body["params"].clone()).unwrap();
+                let nonce = request.signed_transaction.transaction.nonce();
+                sender.send(nonce).unwrap();
+                let error = match ak_nonce {
+                    Some(ak_nonce) if received.fetch_add(1, Ordering::SeqCst) == 0 => json!({
+                        "name": "HANDLER_ERROR",
+                        "cause": {
+                            "name": "INVALID_TRANSACTION",
+                            "info": {
+                                "context": {
+                                    "InvalidNonce": {"tx_nonce": nonce, "ak_nonce": ak_nonce},
+                                },
+                            },
+                        },
+                        "code": -32000,
+                        "message": "Server error",
+                    }),
+                    _ => json!({"code": -32000, "message": "Server error", "data": "rejected"}),
+                };
+                Json(json!({"jsonrpc": "2.0", "id": body["id"], "error": error}))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, receiver)
+    }
+
+    #[tokio::test]
+    async fn test_run_once_sends_nonces_in_order() {
+        let (url, mut receiver) = start_send_tx_rpc(None).await;
+        let (_registry, metrics) = create_registry_and_metrics();
+        let mut engine = Engine::default();
+        // Whichever kind starts first, the slow one finishes preparing last.
+        engine.add_transaction(Arc::new(TestPreparedTransaction {
+            kind: TransactionKind::TokenTransferDefault,
+            prepare_delay: Duration::from_millis(200),
+        }));
+        engine.add_transaction(Arc::new(TestPreparedTransaction {
+            kind: TransactionKind::TokenTransferFinal,
+            prepare_delay: Duration::ZERO,
+        }));
+        let opts = Opts {
+            transaction_kind: vec![
+                TransactionKind::TokenTransferDefault,
+                TransactionKind::TokenTransferFinal,
+            ],
+            kind_parallelism: 2,
+            ..create_test_run_opts()
+        };
+        run_account_transactions_once(
+            engine.transactions().clone(),
+            opts,
+            metrics,
+            Arc::new(SloMonitor::new(
+                HashMap::new(),
+                Duration::from_secs(1),
+                1,
+                None,
+            )),
+            JsonRpcClient::connect(url),
+            create_test_block_tracker(),
+            Arc::new(Nonces::default()),
+        )
+        .await;
+
+        let mut nonces = Vec::new();
+        while let Ok(nonce) = receiver.try_recv() {
+            nonces.push(nonce);
+        }
+        assert_eq!(nonces, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_run_once_retries_invalid_nonce() {
+        let (url, mut receiver) = start_send_tx_rpc(Some(10)).await;
+        let (_registry, metrics) = create_registry_and_metrics();
+        let mut engine = Engine::default();
+        engine.add_transaction(Arc::new(TestPreparedTransaction {
+            kind: TransactionKind::TokenTransferDefault,
+            prepare_delay: Duration::ZERO,
+        }));
+        run_account_transactions_once(
+            engine.transactions().clone(),
+            create_test_run_opts(),
+            metrics,
+            Arc::new(SloMonitor::new(
+                HashMap::new(),
+                Duration::from_secs(1),
+                1,
+                None,
+            )),
+            JsonRpcClient::connect(url),
+            create_test_block_tracker(),
+            Arc::new(Nonces::default()),
+        )
+        .await;
+
+        // Signed again with the nonce following the one of the access key.
+        assert_eq!(receiver.try_recv().unwrap(), 1);
+        assert_eq!(receiver.try_recv().unwrap(), 11);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_network() {
+        // Nothing listens on the discard port, the chain id is never known.
+        let rpc_client = JsonRpcClient::connect("http://127.0.0.1:9");
+        let opts = Opts {
+            network: Some("testnet".to_string()),
+            ..create_test_run_opts()
+        };
+        assert_eq!(resolve_network(&opts, &rpc_client).await, "testnet");
+        let opts = Opts {
+            network: None,
+            ..opts
+        };
+        assert_eq!(resolve_network(&opts, &rpc_client).await, UNKNOWN_NETWORK);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_client_sends_headers() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/",
+            post(move |headers: HeaderMap| async move {
+                sender.send(headers).unwrap();
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let rpc_url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let opts = Opts {
+            rpc_url,
+            rpc_headers: Some(HeaderMap::from_iter([(
+                AUTHORIZATION,
+                HeaderValue::from_static("Bearer token"),
+            )])),
+            rpc_api_key: Some(Secret("key".to_string())),
+            ..create_test_run_opts()
+        };
+        let rpc_client = create_rpc_client(&opts).unwrap();
+        // The stand-in does not answer with JSON-RPC, only the request matters.
+        let _ = rpc_client.call(methods::status::RpcStatusRequest).await;
+
+        let headers = receiver.recv().await.unwrap();
+        assert_eq!(headers[AUTHORIZATION], "Bearer token");
+        assert_eq!(headers["x-api-key"], "key");
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
     }
 
     #[tokio::test]
@@ -467,8 +1410,12 @@ mod tests {
             let mut engine = Engine::default();
             engine.add_transaction(ok_tx_clone);
             engine.add_transaction(err_tx_clone);
+            let opts = Opts {
+                rpc_url: start_chain_rpc().await,
+                ..create_test_run_opts()
+            };
             engine
-                .run(create_test_run_opts(), metrics_clone, shutdown_signal)
+                .run(opts, metrics_clone, shutdown_signal)
                 .await
                 .unwrap();
         });
@@ -477,6 +1424,7 @@ mod tests {
 
         let labels = Labels::new(
             ok_tx.kind().to_string(),
+            String::new(),
             NETWORK.to_string(),
             LOCATION.to_string(),
         );
@@ -496,6 +1444,7 @@ mod tests {
 
         let labels = Labels::new(
             err_tx.kind().to_string(),
+            String::new(),
             NETWORK.to_string(),
             LOCATION.to_string(),
         );
@@ -1,14 +1,14 @@
 use crate::config::Opts;
 use crate::TransactionSample;
 use async_trait::async_trait;
-use near_crypto::InMemorySigner;
 use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
 use near_primitives::action::FunctionCallAction;
 use near_primitives::hash::CryptoHash;
 use near_primitives::transaction::{Action, Transaction, TransactionV0};
 use near_primitives::types::Nonce;
 
-use super::TransactionKind;
+use super::signer::{sign_transaction, Signer};
+use super::{TransactionKind, Variant};
 
 pub struct FungibleTokenTransfer {}
 
@@ -22,16 +22,17 @@ impl TransactionSample for FungibleTokenTransfer {
         "USDT FT transfer"
     }
 
-    fn get_transaction_request(
+    async fn get_transaction_request(
         &self,
-        signer: InMemorySigner,
+        signer: &dyn Signer,
         opts: Opts,
+        variant: &Variant,
         nonce: Nonce,
         block_hash: CryptoHash,
-    ) -> RpcSendTransactionRequest {
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
         let transaction = Transaction::V0(TransactionV0 {
-            signer_id: signer.account_id.clone(),
-            public_key: signer.public_key.clone(),
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
             nonce: nonce + 1,
             receiver_id: opts.ft_account_id,
             block_hash,
@@ -40,13 +41,13 @@ impl TransactionSample for FungibleTokenTransfer {
                 args: serde_json::json!({"amount": "1","receiver_id": opts.receiver_id})
                     .to_string()
                     .into_bytes(),
-                gas: 100_000_000_000_000, // 100 TeraGas
-                deposit: 1,
+                gas: variant.function_call_gas(100_000_000_000_000), // 100 TeraGas
+                deposit: variant.function_call_deposit(1),
             }))],
         });
-        RpcSendTransactionRequest {
-            signed_transaction: transaction.sign(&signer.into()),
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
             wait_until: Default::default(),
-        }
+        })
     }
 }
@@ -1,31 +1,57 @@
 use async_trait::async_trait;
-use near_crypto::InMemorySigner;
 use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
 use near_jsonrpc_primitives::types::transactions::{
     RpcSendTransactionRequest, RpcTransactionError, TransactionInfo,
 };
+use near_primitives::errors::{
+    ActionError, ActionErrorKind, FunctionCallError, HostError, InvalidTxError, TxExecutionError,
+};
 use near_primitives::hash::CryptoHash;
-use near_primitives::types::Nonce;
+use near_primitives::types::{
+    AccountId, Balance, BlockId, BlockReference, FunctionArgs, Gas, Nonce,
+};
+use std::fmt;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use strum_macros::{Display, EnumString};
 use tokio::time::Instant;
-use tracing::{debug, warn};
+use tracing::{debug, info_span, warn, Instrument, Span};
 
-use crate::config::Opts;
+use crate::config::{FunctionCallOverwrite, GasSetting, Opts};
+use crate::gas::{GasEstimator, MAX_GAS};
 use crate::metrics::{Labels, Metrics};
 use near_jsonrpc_client::methods::tx::RpcTransactionResponse;
-use near_primitives::views::{ExecutionStatusView, FinalExecutionStatus};
+use near_primitives::views::{
+    BlockHeaderView, ExecutionStatusView, FinalExecutionOutcomeView, FinalExecutionStatus,
+    QueryRequest,
+};
+use nonces::{send_in_order, Nonces};
+use retry::RetryPolicy;
+use signer::Signer;
 
 pub mod engine;
 
+mod account;
+mod batch;
+mod block_tracker;
+mod calls;
+mod deploy;
 mod fungible_token_transfer;
 mod mpc;
+mod nft;
+pub mod nonces;
+mod relayed;
+mod retry;
+pub mod signer;
 mod swap;
 mod token_transfer_default;
 mod token_transfer_final;
 mod token_transfer_included_final;
 
+#[cfg(test)]
+pub(crate) mod test_utils;
+
 #[derive(clap::ValueEnum, Debug, PartialEq, Eq, Hash, Display, EnumString, Clone)]
 #[strum(serialize_all = "kebab-case")]
 pub enum TransactionKind {
@@ -37,40 +63,278 @@ pub enum TransactionKind {
     MpcSignEcdsa,
     MpcSignEddsa,
     MpcCkd,
+    NftMint,
+    NftTransfer,
+    AccountCreateDelete,
+    AccessKeyAddDelete,
+    ContractDeploy,
+    CallDepth,
+    RelayedTokenTransfer,
+    BatchActions,
+    BatchTransactions,
+}
+
+/// One NEAR in yoctoNEAR.
+const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+
+/// Parameters of one of the transactions a sample executes at every run. Each variant is
+/// reported with its own `variant` label.
+#[derive(Debug, Clone, Default)]
+pub struct Variant {
+    /// Value of the `variant` label, empty for samples with a single variant.
+    pub label: String,
+    /// Sample specific parameters.
+    pub params: serde_json::Value,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.label.is_empty() {
+            Ok(())
+        } else {
+            write!(f, "[{}]", self.label)
+        }
+    }
+}
+
+impl Variant {
+    /// Returns the gas to attach to the function call of a transaction with a single one,
+    /// `default` unless overridden.
+    pub fn function_call_gas(&self, default: Gas) -> Gas {
+        self.function_calls_gas(default, 1)
+    }
+
+    /// Returns the gas to attach to each of the `calls` function calls of a transaction,
+    /// `default` unless overridden. An automatic estimate covers the whole transaction and is
+    /// divided among its calls, and the calls together never exceed `MAX_GAS`.
+    pub fn function_calls_gas(&self, default: Gas, calls: usize) -> Gas {
+        let calls = calls.max(1) as Gas;
+        let gas = match (
+            self.params["gas"].as_u64(),
+            self.params["gas_estimate"].as_u64(),
+        ) {
+            (Some(gas), _) => gas,
+            (None, Some(estimate)) => estimate / calls,
+            (None, None) => default,
+        };
+        gas.min(MAX_GAS / calls)
+    }
+
+    /// Returns the deposit to attach to function calls, `default` unless overridden.
+    pub fn function_call_deposit(&self, default: Balance) -> Balance {
+        self.params["deposit"]
+            .as_str()
+            .and_then(|deposit| deposit.parse().ok())
+            .unwrap_or(default)
+    }
+
+    /// Stores the gas and deposit overriding the defaults of the sample. Automatic gas falls
+    /// back to the default until transactions with these labels were observed.
+    fn with_function_call_overwrite(
+        mut self,
+        overwrite: &FunctionCallOverwrite,
+        estimator: &GasEstimator,
+        labels: &Labels,
+        margin: f64,
+    ) -> Self {
+        match overwrite.gas {
+            Some(GasSetting::Fixed(gas)) => self.params["gas"] = gas.into(),
+            Some(GasSetting::Auto) => {
+                if let Some(estimate) = estimator.estimate(labels, margin) {
+                    self.params["gas_estimate"] = estimate.into();
+                }
+            }
+            None => {}
+        }
+        if let Some(deposit) = overwrite.deposit {
+            // Kept as a string, deposits can exceed the precision of JSON numbers.
+            self.params["deposit"] = deposit.to_string().into();
+        }
+        self
+    }
 }
 
+/// Error of a transaction that was executed successfully, but whose result failed verification.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct InvalidResultError(String);
+
+/// Error of a transaction rejected because its reference block hash is too old. Nothing was
+/// executed, so it can be signed again with a recent block hash and the same nonce.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ExpiredTransactionError(String);
+
+/// Error of a transaction rejected because its nonce was already used, e.g. by a transaction
+/// that reached the RPC node first. Nothing was executed, so it can be signed again with a
+/// fresh nonce.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct InvalidNonceError(String);
+
 #[async_trait]
 pub trait TransactionSample: Send + Sync {
     fn kind(&self) -> TransactionKind;
 
     fn get_name(&self) -> &str;
 
-    fn get_transaction_request(
+    /// Whether the transaction is executed when no transaction kind is explicitly selected.
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
+    /// Whether the signer key stays locked until the transaction is executed, rather than only
+    /// while it is sent, because the signer nonce is used once another account's transaction is
+    /// executed, e.g. by a delegate action.
+    fn locks_key_until_executed(&self) -> bool {
+        false
+    }
+
+    /// Returns the variants executed at every run. A single, unlabeled variant by default.
+    fn variants(&self, _opts: &Opts) -> Vec<Variant> {
+        vec![Variant::default()]
+    }
+
+    /// Builds the transaction of `variant` and signs it, usually with `signer`.
+    async fn get_transaction_request(
         &self,
-        signer: InMemorySigner,
+        signer: &dyn Signer,
         opts: Opts,
+        variant: &Variant,
         nonce: Nonce,
         block_hash: CryptoHash,
-    ) -> RpcSendTransactionRequest;
+    ) -> anyhow::Result<RpcSendTransactionRequest>;
+
+    /// Removes what previous runs of the benchmark left on chain, e.g. after a restart. Called
+    /// once at startup, before the first run.
+    async fn clean_up(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        _opts: &Opts,
+        _block_hash: CryptoHash,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Fetches the chain state the transaction depends on, before its latency is measured.
+    /// The returned variant is the one passed to `get_transaction_request` and `verify_result`.
+    async fn prepare(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        _opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        Ok(variant.clone())
+    }
+
+    /// Checks the value returned by a successful transaction. Any value is accepted by default.
+    /// Invalid values are reported with an `InvalidResultError`, other errors are failures to
+    /// verify the value.
+    async fn verify_result(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        _request: &RpcSendTransactionRequest,
+        _variant: &Variant,
+        _value: &[u8],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Records sample specific metrics from the outcome of a successful transaction.
+    async fn record_outcome(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        _metrics: &Metrics,
+        _labels: &Labels,
+        _sent_at: SystemTime,
+        _outcome: &FinalExecutionOutcomeView,
+    ) {
+    }
 
+    #[allow(clippy::too_many_arguments)]
     async fn execute(
         &self,
         rpc_client: &JsonRpcClient,
         opts: Opts,
+        variant: &Variant,
         metrics: &Arc<Metrics>,
         labels: &Labels,
-        nonce: Nonce,
+        nonces: &Nonces,
         block_hash: CryptoHash,
     ) -> anyhow::Result<Duration> {
-        let now = Instant::now();
+        let mut variant = self.prepare(rpc_client, &opts, variant).await?;
+        if let Some(overwrite) = opts
+            .function_call_overwrite
+            .as_ref()
+            .and_then(|overwrite| overwrite.get(&self.kind()))
+        {
+            variant = variant.with_function_call_overwrite(
+                overwrite,
+                &metrics.gas_estimator,
+                labels,
+                opts.gas_auto_margin,
+            );
+        }
+        let variant = &variant;
 
-        let signer =
-            InMemorySigner::from_secret_key(opts.signer_id.clone(), opts.signer_key.clone());
+        let signer = signer::signer(&opts);
 
-        let request = self.get_transaction_request(signer, opts, nonce, block_hash);
+        // The nonce is reserved once prepared, and the key stays locked until the transaction
+        // is sent, so that a slow preparation does not let a higher nonce be sent first.
+        let (nonce, key_guard) = nonces.reserve().await;
+        // Signing may call a remote service, its latency is reported apart from the transaction.
+        let signing_started = Instant::now();
+        let request = self
+            .get_transaction_request(signer.as_ref(), opts.clone(), variant, nonce, block_hash)
+            .instrument(info_span!("sign"))
+            .await?;
+        metrics
+            .transaction_signing_latency
+            .get_or_create(labels)
+            .observe(signing_started.elapsed().as_secs_f64());
+        Span::current().record(
+            "tx_hash",
+            tracing::field::display(request.signed_transaction.get_hash()),
+        );
+
+        let send = self.send_transaction(
+            rpc_client, &opts, variant, metrics, labels, nonces, &request,
+        );
+        if self.locks_key_until_executed() {
+            let result = send.await;
+            drop(key_guard);
+            result
+        } else {
+            send_in_order(send, key_guard).await
+        }
+    }
 
-        match rpc_client.call(request.clone()).await {
+    /// Sends the signed `request` and waits for its outcome, returning the latency of the
+    /// transaction.
+    #[allow(clippy::too_many_arguments)]
+    async fn send_transaction(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+        metrics: &Arc<Metrics>,
+        labels: &Labels,
+        nonces: &Nonces,
+        request: &RpcSendTransactionRequest,
+    ) -> anyhow::Result<Duration> {
+        let retry = RetryPolicy::from_opts(opts);
+        let now = Instant::now();
+        let sent_at = SystemTime::now();
+        let tx_hash = request.signed_transaction.get_hash();
+
+        let send = retry.call(metrics, labels, || {
+            rpc_client
+                .call(request.clone())
+                .instrument(info_span!("send_tx"))
+        });
+        match send.await {
             Ok(response) => {
+                record_costs(metrics, labels, &response);
                 let successful = is_transaction_successful(&response);
                 debug!("execution outcome: {:?}", &response.final_execution_outcome);
                 debug!(
@@ -80,7 +344,12 @@ pub trait TransactionSample: Send + Sync {
                     successful,
                 );
                 if successful {
-                    Ok(now.elapsed())
+                    let elapsed = now.elapsed();
+                    self.process_response(
+                        rpc_client, metrics, labels, request, variant, &response, sent_at,
+                    )
+                    .await?;
+                    Ok(elapsed)
                 } else {
                     Err(anyhow::anyhow!(
                         "{} failed: unsuccessful execution",
@@ -93,23 +362,53 @@ pub trait TransactionSample: Send + Sync {
                     Some(RpcTransactionError::TimeoutError) => {
                         metrics.timeouts.get_or_create(labels).inc();
                     }
+                    Some(RpcTransactionError::InvalidTransaction {
+                        context: InvalidTxError::Expired,
+                    }) => {
+                        return Err(ExpiredTransactionError(format!(
+                            "{} failed: transaction expired",
+                            self.get_name()
+                        ))
+                        .into());
+                    }
+                    Some(RpcTransactionError::InvalidTransaction {
+                        context: InvalidTxError::InvalidNonce { ak_nonce, .. },
+                    }) => {
+                        // Nonces of other keys, e.g. of the receiver, or of the signer key
+                        // added to other accounts, e.g. sub-accounts, are not handed out here.
+                        let transaction = &request.signed_transaction.transaction;
+                        if *transaction.signer_id() == opts.signer_id
+                            && *transaction.public_key() == signer::signer(opts).public_key()
+                        {
+                            nonces.observe(*ak_nonce).await;
+                        }
+                        return Err(InvalidNonceError(format!(
+                            "{} failed: invalid nonce",
+                            self.get_name()
+                        ))
+                        .into());
+                    }
                     _ => {
                         warn!("failure during {}:\n{}\n", self.get_name(), err);
                         return Err(anyhow::anyhow!("{} failed: {}", self.get_name(), err));
                     }
                 }
                 loop {
-                    match rpc_client
-                        .call(methods::tx::RpcTransactionStatusRequest {
-                            transaction_info: TransactionInfo::TransactionId {
-                                tx_hash: request.signed_transaction.get_hash(),
-                                sender_account_id: request
-                                    .signed_transaction
-                                    .transaction
-                                    .signer_id()
-                                    .clone(),
-                            },
-                            wait_until: request.wait_until.clone(),
+                    match retry
+                        .call(metrics, labels, || {
+                            rpc_client
+                                .call(methods::tx::RpcTransactionStatusRequest {
+                                    transaction_info: TransactionInfo::TransactionId {
+                                        tx_hash,
+                                        sender_account_id: request
+                                            .signed_transaction
+                                            .transaction
+                                            .signer_id()
+                                            .clone(),
+                                    },
+                                    wait_until: request.wait_until.clone(),
+                                })
+                                .instrument(info_span!("tx_status"))
                         })
                         .await
                     {
@@ -127,18 +426,174 @@ pub trait TransactionSample: Send + Sync {
                             }
                         },
                         Ok(response) => {
+                            record_costs(metrics, labels, &response);
                             debug!(
                                 "successful {}, status: {:?}\n",
                                 self.get_name(),
                                 response.final_execution_status,
                             );
-                            return Ok(now.elapsed());
+                            let elapsed = now.elapsed();
+                            self.process_response(
+                                rpc_client, metrics, labels, request, variant, &response, sent_at,
+                            )
+                            .await?;
+                            return Ok(elapsed);
                         }
                     }
                 }
             }
         }
     }
+
+    /// Runs `verify_result` on the value returned by the transaction, if there is one, then
+    /// `record_outcome`.
+    #[allow(clippy::too_many_arguments)]
+    async fn process_response(
+        &self,
+        rpc_client: &JsonRpcClient,
+        metrics: &Metrics,
+        labels: &Labels,
+        request: &RpcSendTransactionRequest,
+        variant: &Variant,
+        response: &RpcTransactionResponse,
+        sent_at: SystemTime,
+    ) -> anyhow::Result<()> {
+        let Some(outcome_view) = &response.final_execution_outcome else {
+            return Ok(());
+        };
+        let outcome = outcome_view.clone().into_outcome();
+        if let FinalExecutionStatus::SuccessValue(value) = &outcome.status {
+            self.verify_result(rpc_client, request, variant, value)
+                .await
+                .map_err(|err| match err.downcast::<InvalidResultError>() {
+                    Ok(err) => InvalidResultError(format!(
+                        "{} failed: invalid result: {}",
+                        self.get_name(),
+                        err
+                    ))
+                    .into(),
+                    // E.g. the RPC failed while fetching what the result is checked against.
+                    Err(err) => anyhow::anyhow!(
+                        "{} failed: could not verify result: {}",
+                        self.get_name(),
+                        err
+                    ),
+                })?;
+        }
+        metrics
+            .receipts
+            .get_or_create(labels)
+            .observe(outcome.receipts_outcome.len() as f64);
+        self.record_outcome(rpc_client, metrics, labels, sent_at, &outcome)
+            .await;
+        Ok(())
+    }
+}
+
+/// Calls a view function and returns its raw result.
+pub(crate) async fn view_function(
+    rpc_client: &JsonRpcClient,
+    contract_id: &AccountId,
+    method_name: &str,
+    args: serde_json::Value,
+) -> anyhow::Result<Vec<u8>> {
+    let response = rpc_client
+        .call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::CallFunction {
+                account_id: contract_id.clone(),
+                method_name: method_name.to_string(),
+                args: FunctionArgs::from(args.to_string().into_bytes()),
+            },
+        })
+        .await?;
+    match response.kind {
+        QueryResponseKind::CallResult(result) => Ok(result.result),
+        _ => anyhow::bail!("unexpected response to {} view call", method_name),
+    }
+}
+
+/// Returns the nonce of the access key used by `signer`.
+pub(crate) async fn access_key_nonce(
+    rpc_client: &JsonRpcClient,
+    signer: &dyn Signer,
+) -> anyhow::Result<Nonce> {
+    let response = rpc_client
+        .call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccessKey {
+                account_id: signer.account_id().clone(),
+                public_key: signer.public_key(),
+            },
+        })
+        .await?;
+    match response.kind {
+        QueryResponseKind::AccessKey(access_key) => Ok(access_key.nonce),
+        _ => anyhow::bail!("could not retrieve access key for {}", signer.account_id()),
+    }
+}
+
+/// Fetches the header of the block with the given hash.
+pub(crate) async fn block_header(
+    rpc_client: &JsonRpcClient,
+    block_hash: CryptoHash,
+) -> anyhow::Result<BlockHeaderView> {
+    let block = rpc_client
+        .call(methods::block::RpcBlockRequest {
+            block_reference: BlockReference::BlockId(BlockId::Hash(block_hash)),
+        })
+        .await?;
+    Ok(block.header)
+}
+
+/// Records the gas and tokens burnt by the transaction and all its receipts, whether it
+/// succeeded or not.
+fn record_costs(metrics: &Metrics, labels: &Labels, response: &RpcTransactionResponse) {
+    let Some(outcome_view) = &response.final_execution_outcome else {
+        return;
+    };
+    let outcome = outcome_view.clone().into_outcome();
+    let (gas_burnt, tokens_burnt) = std::iter::once(&outcome.transaction_outcome)
+        .chain(&outcome.receipts_outcome)
+        .fold((0, 0), |(gas, tokens), outcome| {
+            (
+                gas + outcome.outcome.gas_burnt,
+                tokens + outcome.outcome.tokens_burnt,
+            )
+        });
+    metrics.gas_burnt.get_or_create(labels).inc_by(gas_burnt);
+    // A call out of gas burnt all it was given, so recording it raises the estimate by the
+    // margin at every such failure, up to the maximum, until the calls succeed again.
+    if is_transaction_successful(response) || exceeded_prepaid_gas(&outcome) {
+        metrics.gas_estimator.record(labels, gas_burnt);
+    }
+    metrics
+        .gas_burnt_per_transaction
+        .get_or_create(labels)
+        .observe(gas_burnt as f64);
+    metrics
+        .tokens_burnt
+        .get_or_create(labels)
+        .inc_by(tokens_burnt as f64 / ONE_NEAR as f64);
+}
+
+/// Returns whether a function call of the transaction failed for exceeding its prepaid gas.
+fn exceeded_prepaid_gas(outcome: &FinalExecutionOutcomeView) -> bool {
+    std::iter::once(&outcome.transaction_outcome)
+        .chain(&outcome.receipts_outcome)
+        .any(|outcome| match &outcome.outcome.status {
+            ExecutionStatusView::Failure(TxExecutionError::ActionError(ActionError {
+                kind: ActionErrorKind::FunctionCallError(err),
+                ..
+            })) => match err {
+                FunctionCallError::ExecutionError(message) => {
+                    message.starts_with("Exceeded the prepaid gas")
+                }
+                FunctionCallError::HostError(HostError::GasExceeded) => true,
+                _ => false,
+            },
+            _ => false,
+        })
 }
 
 fn is_transaction_successful(response: &RpcTransactionResponse) -> bool {
@@ -161,3 +616,237 @@ fn is_transaction_successful(response: &RpcTransactionResponse) -> bool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::views::{FinalExecutionOutcomeViewEnum, TxExecutionStatus};
+
+    use crate::metrics::create_registry_and_metrics;
+    use crate::transaction::signer::signer_for;
+    use crate::transaction::test_utils::{
+        create_test_final_outcome, create_test_outcome, create_test_run_opts, start_test_rpc,
+    };
+    use crate::transaction::token_transfer_default::TokenTransferDefault;
+
+    use super::*;
+
+    #[test]
+    fn test_record_costs() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let labels = Labels::new(
+            "swap".to_string(),
+            String::new(),
+            "mainnet".to_string(),
+            "eu".to_string(),
+        );
+        let outcome = |gas_burnt| {
+            create_test_outcome(
+                CryptoHash::new(),
+                CryptoHash::new(),
+                gas_burnt,
+                ExecutionStatusView::SuccessValue(vec![]),
+            )
+        };
+        let response = RpcTransactionResponse {
+            final_execution_outcome: Some(FinalExecutionOutcomeViewEnum::FinalExecutionOutcome(
+                create_test_final_outcome(
+                    outcome(1_000_000_000_000),
+                    vec![outcome(2_000_000_000_000), outcome(3_000_000_000_000)],
+                ),
+            )),
+            final_execution_status: TxExecutionStatus::Final,
+        };
+        record_costs(&metrics, &labels, &response);
+        record_costs(&metrics, &labels, &response);
+        assert_eq!(
+            metrics.gas_burnt.get_or_create(&labels).get(),
+            12_000_000_000_000
+        );
+        // Test outcomes burn 1e8 yoctoNEAR per gas, 6e20 per transaction.
+        assert!((metrics.tokens_burnt.get_or_create(&labels).get() - 0.0012).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_gas_estimate_recovers_from_exceeded_gas() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let labels = Labels::new(
+            "swap".to_string(),
+            String::new(),
+            "mainnet".to_string(),
+            "eu".to_string(),
+        );
+        let response = |gas_burnt, status| RpcTransactionResponse {
+            final_execution_outcome: Some(FinalExecutionOutcomeViewEnum::FinalExecutionOutcome(
+                create_test_final_outcome(
+                    create_test_outcome(
+                        CryptoHash::new(),
+                        CryptoHash::new(),
+                        1_000_000_000_000,
+                        ExecutionStatusView::SuccessReceiptId(CryptoHash::new()),
+                    ),
+                    vec![create_test_outcome(
+                        CryptoHash::new(),
+                        CryptoHash::new(),
+                        gas_burnt,
+                        status,
+                    )],
+                ),
+            )),
+            final_execution_status: TxExecutionStatus::Final,
+        };
+        let out_of_gas = || {
+            ExecutionStatusView::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(0),
+                kind: ActionErrorKind::FunctionCallError(FunctionCallError::ExecutionError(
+                    "Exceeded the prepaid gas.".to_string(),
+                )),
+            }))
+        };
+        let auto = FunctionCallOverwrite {
+            gas: Some(GasSetting::Auto),
+            deposit: None,
+        };
+        let gas = || {
+            Variant::default()
+                .with_function_call_overwrite(&auto, &metrics.gas_estimator, &labels, 0.5)
+                .function_call_gas(10)
+        };
+        record_costs(
+            &metrics,
+            &labels,
+            &response(9_000_000_000_000, ExecutionStatusView::SuccessValue(vec![])),
+        );
+        assert_eq!(gas(), 15_000_000_000_000);
+
+        // The calls now need more gas, every failure burns all the gas attached.
+        let mut attempts = 0;
+        while gas() < MAX_GAS {
+            record_costs(
+                &metrics,
+                &labels,
+                &response(gas() - 1_000_000_000_000, out_of_gas()),
+            );
+            attempts += 1;
+        }
+        assert_eq!(attempts, 8);
+
+        // Other failures, e.g. a panic of the contract, are not learnt from.
+        let (_registry, metrics) = create_registry_and_metrics();
+        let panic = ExecutionStatusView::Failure(TxExecutionError::ActionError(ActionError {
+            index: Some(0),
+            kind: ActionErrorKind::FunctionCallError(FunctionCallError::ExecutionError(
+                "Smart contract panicked: test".to_string(),
+            )),
+        }));
+        record_costs(&metrics, &labels, &response(9_000_000_000_000, panic));
+        assert_eq!(metrics.gas_estimator.estimate(&labels, 0.5), None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_nonce_observed_for_signer_only() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let opts = create_test_run_opts();
+        let labels = Labels::new(
+            "token-transfer-default".to_string(),
+            String::new(),
+            "localnet".to_string(),
+            "eu".to_string(),
+        );
+        let url = start_test_rpc(|method, params| {
+            assert_eq!(method, "send_tx");
+            let request: RpcSendTransactionRequest =
+                serde_json::from_value(params.clone()).unwrap();
+            let nonce = request.signed_transaction.transaction.nonce();
+            Err(serde_json::json!({
+                "name": "HANDLER_ERROR",
+                "cause": {
+                    "name": "INVALID_TRANSACTION",
+                    "info": {"context": {"InvalidNonce": {"tx_nonce": nonce, "ak_nonce": 10}}},
+                },
+                "code": -32000,
+                "message": "Server error",
+            }))
+        })
+        .await;
+        let rpc_client = JsonRpcClient::connect(url);
+        let nonces = Nonces::default();
+        // The signer key is also added to sub-accounts, whose nonces are unrelated.
+        let sub_account = signer_for(&opts, "sub.cat.near".parse().unwrap());
+        for (signer, expected) in [(sub_account, 0), (signer::signer(&opts), 10)] {
+            let request = TokenTransferDefault {}
+                .get_transaction_request(
+                    signer.as_ref(),
+                    opts.clone(),
+                    &Variant::default(),
+                    0,
+                    CryptoHash::new(),
+                )
+                .await
+                .unwrap();
+            let err = TokenTransferDefault {}
+                .send_transaction(
+                    &rpc_client,
+                    &opts,
+                    &Variant::default(),
+                    &metrics,
+                    &labels,
+                    &nonces,
+                    &request,
+                )
+                .await
+                .unwrap_err();
+            assert!(err.is::<InvalidNonceError>());
+            let (nonce, _guard) = nonces.reserve_many(0).await;
+            assert_eq!(nonce, expected);
+        }
+    }
+
+    #[test]
+    fn test_function_call_overwrite() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let labels = Labels::new(
+            "swap".to_string(),
+            String::new(),
+            "mainnet".to_string(),
+            "eu".to_string(),
+        );
+        let variant = Variant::default();
+        assert_eq!(variant.function_call_gas(10), 10);
+        assert_eq!(variant.function_call_deposit(1), 1);
+
+        let fixed = FunctionCallOverwrite {
+            gas: Some(GasSetting::Fixed(20)),
+            deposit: Some(ONE_NEAR),
+        };
+        let variant = Variant::default().with_function_call_overwrite(
+            &fixed,
+            &metrics.gas_estimator,
+            &labels,
+            0.5,
+        );
+        assert_eq!(variant.function_call_gas(10), 20);
+        assert_eq!(variant.function_call_deposit(1), ONE_NEAR);
+
+        let auto = FunctionCallOverwrite {
+            gas: Some(GasSetting::Auto),
+            deposit: None,
+        };
+        let variant = Variant::default().with_function_call_overwrite(
+            &auto,
+            &metrics.gas_estimator,
+            &labels,
+            0.5,
+        );
+        assert_eq!(variant.function_call_gas(10), 10);
+        metrics.gas_estimator.record(&labels, 40);
+        let variant = Variant::default().with_function_call_overwrite(
+            &auto,
+            &metrics.gas_estimator,
+            &labels,
+            0.5,
+        );
+        assert_eq!(variant.function_call_gas(10), 60);
+        assert_eq!(variant.function_calls_gas(10, 3), 20);
+        assert_eq!(variant.function_call_deposit(1), 1);
+    }
+}
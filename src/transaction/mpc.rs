@@ -1,19 +1,29 @@
-use super::TransactionKind;
+use super::signer::{sign_transaction, Signer};
+use super::{InvalidResultError, TransactionKind, Variant};
 use crate::config::Opts;
+use crate::metrics::{Labels, Metrics};
+use crate::transaction::{block_header, view_function};
 use crate::TransactionSample;
 use async_trait::async_trait;
-use near_crypto::InMemorySigner;
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use near_crypto::{KeyType, PublicKey, Signature};
+use near_jsonrpc_client::JsonRpcClient;
 use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
 use near_primitives::action::FunctionCallAction;
 use near_primitives::hash::CryptoHash;
 use near_primitives::transaction::{Action, Transaction, TransactionV0};
 use near_primitives::types::Nonce;
-use near_primitives::views::TxExecutionStatus;
+use near_primitives::views::{ExecutionStatusView, FinalExecutionOutcomeView, TxExecutionStatus};
 use rand::Rng;
+use serde_json::Value;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const BLS12381G1_PREFIX: &str = "bls12381g1:";
 
 pub struct MpcSignEcdsa {}
 pub struct MpcSignEddsa {}
-#[allow(unused)]
 pub struct MpcCkd {}
 
 #[async_trait]
@@ -26,37 +36,62 @@ impl TransactionSample for MpcSignEcdsa {
         "Call MPC ecdsa sign function"
     }
 
-    fn get_transaction_request(
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        sign_variants(&opts.mpc_ecdsa_domains, &opts.mpc_paths)
+    }
+
+    async fn get_transaction_request(
         &self,
-        signer: InMemorySigner,
+        signer: &dyn Signer,
         opts: Opts,
+        variant: &Variant,
         nonce: Nonce,
         block_hash: CryptoHash,
-    ) -> RpcSendTransactionRequest {
-        let domain_id = 0;
-        let mut rng = rand::thread_rng();
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let domain_id = &variant.params["domain_id"];
         let mut random_bytes = [0u8; 32];
-        rng.fill(&mut random_bytes);
+        rand::thread_rng().fill(&mut random_bytes);
         let payload = hex::encode(random_bytes);
         let transaction = Transaction::V0(TransactionV0 {
-            signer_id: signer.account_id.clone(),
-            public_key: signer.public_key.clone(),
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
             nonce: nonce + 1,
             receiver_id: opts.mpc_contract_id,
             block_hash,
             actions: vec![Action::FunctionCall(Box::new(FunctionCallAction {
                 method_name: "sign".to_string(),
-                args: serde_json::json!({"request": {"domain_id": domain_id,"path": "","payload_v2": {"Ecdsa": payload}}})
+                args: serde_json::json!({"request": {"domain_id": domain_id,"path": request_path(variant),"payload_v2": {"Ecdsa": payload}}})
                     .to_string()
                     .into_bytes(),
-                gas: 15_000_000_000_000, // 15 TeraGas
-                deposit: 1,
+                gas: variant.function_call_gas(15_000_000_000_000), // 15 TeraGas
+                deposit: variant.function_call_deposit(1),
             }))],
         });
-        RpcSendTransactionRequest {
-            signed_transaction: transaction.sign(&signer.into()),
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
             wait_until: TxExecutionStatus::Final,
-        }
+        })
+    }
+
+    async fn verify_result(
+        &self,
+        rpc_client: &JsonRpcClient,
+        request: &RpcSendTransactionRequest,
+        _variant: &Variant,
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        verify_sign_response(rpc_client, request, value).await
+    }
+
+    async fn record_outcome(
+        &self,
+        rpc_client: &JsonRpcClient,
+        metrics: &Metrics,
+        labels: &Labels,
+        sent_at: SystemTime,
+        outcome: &FinalExecutionOutcomeView,
+    ) {
+        record_yield_resume_latency(rpc_client, metrics, labels, sent_at, outcome).await;
     }
 }
 
@@ -70,37 +105,62 @@ impl TransactionSample for MpcSignEddsa {
         "Call MPC eddsa sign function"
     }
 
-    fn get_transaction_request(
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        sign_variants(&opts.mpc_eddsa_domains, &opts.mpc_paths)
+    }
+
+    async fn get_transaction_request(
         &self,
-        signer: InMemorySigner,
+        signer: &dyn Signer,
         opts: Opts,
+        variant: &Variant,
         nonce: Nonce,
         block_hash: CryptoHash,
-    ) -> RpcSendTransactionRequest {
-        let domain_id = 1;
-        let mut rng = rand::thread_rng();
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let domain_id = &variant.params["domain_id"];
         let mut random_bytes = [0u8; 32];
-        rng.fill(&mut random_bytes);
+        rand::thread_rng().fill(&mut random_bytes);
         let payload = hex::encode(random_bytes);
         let transaction = Transaction::V0(TransactionV0 {
-            signer_id: signer.account_id.clone(),
-            public_key: signer.public_key.clone(),
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
             nonce: nonce + 1,
             receiver_id: opts.mpc_contract_id,
             block_hash,
             actions: vec![Action::FunctionCall(Box::new(FunctionCallAction {
                 method_name: "sign".to_string(),
-                args: serde_json::json!({"request": {"domain_id": domain_id,"path": "","payload_v2": {"Eddsa": payload}}})
+                args: serde_json::json!({"request": {"domain_id": domain_id,"path": request_path(variant),"payload_v2": {"Eddsa": payload}}})
                     .to_string()
                     .into_bytes(),
-                gas: 15_000_000_000_000, // 15 TeraGas
-                deposit: 1,
+                gas: variant.function_call_gas(15_000_000_000_000), // 15 TeraGas
+                deposit: variant.function_call_deposit(1),
             }))],
         });
-        RpcSendTransactionRequest {
-            signed_transaction: transaction.sign(&signer.into()),
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
             wait_until: TxExecutionStatus::Final,
-        }
+        })
+    }
+
+    async fn verify_result(
+        &self,
+        rpc_client: &JsonRpcClient,
+        request: &RpcSendTransactionRequest,
+        _variant: &Variant,
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        verify_sign_response(rpc_client, request, value).await
+    }
+
+    async fn record_outcome(
+        &self,
+        rpc_client: &JsonRpcClient,
+        metrics: &Metrics,
+        labels: &Labels,
+        sent_at: SystemTime,
+        outcome: &FinalExecutionOutcomeView,
+    ) {
+        record_yield_resume_latency(rpc_client, metrics, labels, sent_at, outcome).await;
     }
 }
 
@@ -114,24 +174,29 @@ impl TransactionSample for MpcCkd {
         "Call MPC ckd function"
     }
 
-    fn get_transaction_request(
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        domain_variants(&opts.mpc_ckd_domains)
+    }
+
+    async fn get_transaction_request(
         &self,
-        signer: InMemorySigner,
+        signer: &dyn Signer,
         opts: Opts,
+        variant: &Variant,
         nonce: Nonce,
         block_hash: CryptoHash,
-    ) -> RpcSendTransactionRequest {
-        let domain_id = 2;
-        // To generate this value randomly we need to add a few dependencies
-        // This means that if a request is sent before the previous
-        // one is replied, it might fail as the nodes optimize repeated requests.
-        // This should not be a problem here, as we should only submit one ckd request
-        // every 5 minutes, and we expect them to be replied in less than a minute.
-        let app_public_key =
-            "bls12381g1:6KtVVcAAGacrjNGePN8bp3KV6fYGrw1rFsyc7cVJCqR16Zc2ZFg3HX3hSZxSfv1oH6";
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let domain_id = &variant.params["domain_id"];
+        // A fresh app key for every request, otherwise the MPC nodes deduplicate requests
+        // submitted before the previous one is answered.
+        let app_public_key = random_app_public_key();
         let transaction = Transaction::V0(TransactionV0 {
-            signer_id: signer.account_id.clone(),
-            public_key: signer.public_key.clone(),
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
             nonce: nonce + 1,
             receiver_id: opts.mpc_contract_id,
             block_hash,
@@ -140,13 +205,472 @@ impl TransactionSample for MpcCkd {
                 args: serde_json::json!({"request": {"domain_id": domain_id,"app_public_key": app_public_key}})
                     .to_string()
                     .into_bytes(),
-                gas: 15_000_000_000_000, // 15 TeraGas
-                deposit: 1,
+                gas: variant.function_call_gas(15_000_000_000_000), // 15 TeraGas
+                deposit: variant.function_call_deposit(1),
             }))],
         });
-        RpcSendTransactionRequest {
-            signed_transaction: transaction.sign(&signer.into()),
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
             wait_until: TxExecutionStatus::Final,
+        })
+    }
+
+    async fn verify_result(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        _request: &RpcSendTransactionRequest,
+        _variant: &Variant,
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        let response: serde_json::Value = serde_json::from_slice(value)
+            .map_err(|err| InvalidResultError(format!("invalid ckd response: {}", err)))?;
+        for field in ["big_y", "big_c"] {
+            let point = response[field]
+                .as_str()
+                .ok_or_else(|| InvalidResultError(format!("missing {} in ckd response", field)))?;
+            parse_g1_point(point)
+                .map_err(|err| InvalidResultError(format!("invalid {}: {}", field, err)))?;
+        }
+        Ok(())
+    }
+
+    async fn record_outcome(
+        &self,
+        rpc_client: &JsonRpcClient,
+        metrics: &Metrics,
+        labels: &Labels,
+        sent_at: SystemTime,
+        outcome: &FinalExecutionOutcomeView,
+    ) {
+        record_yield_resume_latency(rpc_client, metrics, labels, sent_at, outcome).await;
+    }
+}
+
+const RANDOM_PATH: &str = "random";
+
+/// Returns one variant per domain.
+fn domain_variants(domains: &[u64]) -> Vec<Variant> {
+    domains
+        .iter()
+        .map(|domain_id| Variant {
+            label: format!("domain-{}", domain_id),
+            params: serde_json::json!({ "domain_id": domain_id }),
+        })
+        .collect()
+}
+
+/// Returns one variant per domain and distinct derivation path, labeled by the class of the path.
+/// Fixed paths are numbered in their configured order, so each has its own metric series.
+fn sign_variants(domains: &[u64], paths: &[String]) -> Vec<Variant> {
+    let mut distinct_paths: Vec<&String> = vec![];
+    for path in paths {
+        if !distinct_paths.contains(&path) {
+            distinct_paths.push(path);
         }
     }
+    let mut variants = vec![];
+    for domain_id in domains {
+        let mut fixed_paths = 0;
+        for path in &distinct_paths {
+            let path_class = match path.as_str() {
+                "" => "empty-path".to_string(),
+                RANDOM_PATH => "random-path".to_string(),
+                _ => {
+                    fixed_paths += 1;
+                    format!("fixed-path-{}", fixed_paths - 1)
+                }
+            };
+            variants.push(Variant {
+                label: format!("domain-{}/{}", domain_id, path_class),
+                params: serde_json::json!({ "domain_id": domain_id, "path": path }),
+            });
+        }
+    }
+    variants
+}
+
+/// Returns the derivation path of a sign request, generating a fresh one for random paths.
+fn request_path(variant: &Variant) -> String {
+    match variant.params["path"].as_str().unwrap_or_default() {
+        RANDOM_PATH => {
+            let mut random_bytes = [0u8; 16];
+            rand::thread_rng().fill(&mut random_bytes);
+            hex::encode(random_bytes)
+        }
+        path => path.to_string(),
+    }
+}
+
+/// Checks that the signature returned by the MPC contract signs the requested payload with the
+/// public key derived for the signer and the request path.
+async fn verify_sign_response(
+    rpc_client: &JsonRpcClient,
+    request: &RpcSendTransactionRequest,
+    value: &[u8],
+) -> anyhow::Result<()> {
+    let transaction = &request.signed_transaction.transaction;
+    let sign_request = match transaction.actions().first() {
+        Some(Action::FunctionCall(call)) => {
+            serde_json::from_slice::<Value>(&call.args)?["request"].clone()
+        }
+        _ => anyhow::bail!("sign request has no function call"),
+    };
+    let public_key = view_function(
+        rpc_client,
+        transaction.receiver_id(),
+        "derived_public_key",
+        serde_json::json!({
+            "path": sign_request["path"],
+            "predecessor": transaction.signer_id(),
+            "domain_id": sign_request["domain_id"],
+        }),
+    )
+    .await?;
+    let public_key = PublicKey::from_str(&serde_json::from_slice::<String>(&public_key)?)?;
+    // Only the response is checked from here on, any error makes the result invalid.
+    verify_signature(value, &sign_request, &public_key)
+        .map_err(|err| InvalidResultError(err.to_string()).into())
+}
+
+fn verify_signature(
+    value: &[u8],
+    sign_request: &Value,
+    public_key: &PublicKey,
+) -> anyhow::Result<()> {
+    let response: Value = serde_json::from_slice(value)?;
+    let (payload, signature) = match public_key.key_type() {
+        KeyType::SECP256K1 => {
+            let big_r = hex::decode(json_str(&response["big_r"]["affine_point"])?)?;
+            let s = hex::decode(json_str(&response["s"]["scalar"])?)?;
+            let recovery_id = response["recovery_id"]
+                .as_u64()
+                .ok_or_else(|| anyhow::anyhow!("missing recovery_id"))?;
+            anyhow::ensure!(
+                big_r.len() == 33 && s.len() == 32,
+                "invalid signature length"
+            );
+            // near-crypto expects the x coordinate of R, s and the recovery id.
+            let mut data = big_r[1..].to_vec();
+            data.extend(s);
+            data.push(recovery_id as u8);
+            (
+                &sign_request["payload_v2"]["Ecdsa"],
+                Signature::from_parts(KeyType::SECP256K1, &data)?,
+            )
+        }
+        KeyType::ED25519 => {
+            let data: Vec<u8> = serde_json::from_value(response["signature"].clone())?;
+            (
+                &sign_request["payload_v2"]["Eddsa"],
+                Signature::from_parts(KeyType::ED25519, &data)?,
+            )
+        }
+    };
+    let payload = hex::decode(json_str(payload)?)?;
+    anyhow::ensure!(
+        signature.verify(&payload, public_key),
+        "signature does not match the derived public key {}",
+        public_key
+    );
+    Ok(())
+}
+
+fn json_str(value: &Value) -> anyhow::Result<&str> {
+    value
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("expected a string, found {}", value))
+}
+
+/// Reports how long the request waited before being queued by the MPC contract, how long the
+/// MPC network took to respond, and the number of blocks between the yield and the resume.
+async fn record_yield_resume_latency(
+    rpc_client: &JsonRpcClient,
+    metrics: &Metrics,
+    labels: &Labels,
+    sent_at: SystemTime,
+    outcome: &FinalExecutionOutcomeView,
+) {
+    let Some((yield_block, resume_block)) = find_yield_resume_blocks(outcome) else {
+        warn!(
+            "no yield and resume receipts in the outcome of {}",
+            outcome.transaction.hash
+        );
+        return;
+    };
+    let (yield_header, resume_header) = match tokio::try_join!(
+        block_header(rpc_client, yield_block),
+        block_header(rpc_client, resume_block)
+    ) {
+        Ok(headers) => headers,
+        Err(err) => {
+            warn!("failed to fetch yield and resume blocks: {}", err);
+            return;
+        }
+    };
+    let yield_time = UNIX_EPOCH + Duration::from_nanos(yield_header.timestamp_nanosec);
+    let resume_time = UNIX_EPOCH + Duration::from_nanos(resume_header.timestamp_nanosec);
+    // Block timestamps come from the validators' clocks, which may be slightly ahead of ours.
+    let queue_latency = yield_time.duration_since(sent_at).unwrap_or_default();
+    let signing_latency = resume_time.duration_since(yield_time).unwrap_or_default();
+    metrics
+        .mpc_queue_latency
+        .get_or_create(labels)
+        .observe(queue_latency.as_secs_f64());
+    metrics
+        .mpc_signing_latency
+        .get_or_create(labels)
+        .observe(signing_latency.as_secs_f64());
+    metrics
+        .mpc_blocks_elapsed
+        .get_or_create(labels)
+        .observe(resume_header.height.saturating_sub(yield_header.height) as f64);
+}
+
+/// Returns the blocks of the request receipt, which yields, and of the callback receipt resumed
+/// once the MPC network responded.
+fn find_yield_resume_blocks(
+    outcome: &FinalExecutionOutcomeView,
+) -> Option<(CryptoHash, CryptoHash)> {
+    let find_child = |status: &ExecutionStatusView| match status {
+        ExecutionStatusView::SuccessReceiptId(receipt_id) => outcome
+            .receipts_outcome
+            .iter()
+            .find(|receipt| receipt.id == *receipt_id),
+        _ => None,
+    };
+    let yield_receipt = find_child(&outcome.transaction_outcome.outcome.status)?;
+    let resume_receipt = find_child(&yield_receipt.outcome.status)?;
+    Some((yield_receipt.block_hash, resume_receipt.block_hash))
+}
+
+/// Returns a random BLS12-381 G1 public key, in the format expected by the MPC contract.
+fn random_app_public_key() -> String {
+    let mut random_bytes = [0u8; 64];
+    rand::thread_rng().fill(&mut random_bytes);
+    let point = G1Projective::generator() * Scalar::from_bytes_wide(&random_bytes);
+    format_g1_point(&G1Affine::from(point))
+}
+
+fn format_g1_point(point: &G1Affine) -> String {
+    format!(
+        "{}{}",
+        BLS12381G1_PREFIX,
+        bs58::encode(point.to_compressed()).into_string()
+    )
+}
+
+fn parse_g1_point(s: &str) -> anyhow::Result<G1Affine> {
+    let encoded = s
+        .strip_prefix(BLS12381G1_PREFIX)
+        .ok_or_else(|| anyhow::anyhow!("missing {} prefix", BLS12381G1_PREFIX))?;
+    let bytes: [u8; 48] = bs58::decode(encoded)
+        .into_vec()?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected 48 bytes"))?;
+    Option::from(G1Affine::from_compressed(&bytes))
+        .ok_or_else(|| anyhow::anyhow!("not a point of the G1 subgroup"))
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::SecretKey;
+
+    use crate::transaction::test_utils::{
+        create_test_final_outcome, create_test_outcome, create_test_request,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_random_app_public_key() {
+        let key = random_app_public_key();
+        assert!(parse_g1_point(&key).is_ok());
+        assert_ne!(key, random_app_public_key());
+    }
+
+    /// Returns the raw bytes of a signature, encoded as `<key type>:<base58 data>`.
+    fn signature_bytes(signature: &Signature) -> Vec<u8> {
+        let encoded = signature.to_string();
+        let (_, data) = encoded.split_once(':').unwrap();
+        bs58::decode(data).into_vec().unwrap()
+    }
+
+    #[test]
+    fn test_verify_ecdsa_signature() {
+        let secret_key = SecretKey::from_random(KeyType::SECP256K1);
+        let payload = [7u8; 32];
+        let sign_request = serde_json::json!({"payload_v2": {"Ecdsa": hex::encode(payload)}});
+        let signature = signature_bytes(&secret_key.sign(&payload));
+        let response = serde_json::json!({
+            "scheme": "Secp256k1",
+            "big_r": {"affine_point": format!("02{}", hex::encode(&signature[..32]))},
+            "s": {"scalar": hex::encode(&signature[32..64])},
+            "recovery_id": signature[64],
+        });
+        let value = response.to_string().into_bytes();
+        assert!(verify_signature(&value, &sign_request, &secret_key.public_key()).is_ok());
+
+        let other_key = SecretKey::from_random(KeyType::SECP256K1).public_key();
+        assert!(verify_signature(&value, &sign_request, &other_key).is_err());
+    }
+
+    #[test]
+    fn test_verify_eddsa_signature() {
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        let payload = [7u8; 32];
+        let sign_request = serde_json::json!({"payload_v2": {"Eddsa": hex::encode(payload)}});
+        let response = serde_json::json!({
+            "scheme": "Ed25519",
+            "signature": signature_bytes(&secret_key.sign(&payload)),
+        });
+        let value = response.to_string().into_bytes();
+        assert!(verify_signature(&value, &sign_request, &secret_key.public_key()).is_ok());
+
+        let other_payload = serde_json::json!({"payload_v2": {"Eddsa": hex::encode([8u8; 32])}});
+        assert!(verify_signature(&value, &other_payload, &secret_key.public_key()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sign_verify_result_rpc_failure_is_not_invalid() {
+        // Nothing listens on the discard port, the derived public key cannot be fetched.
+        let rpc_client = JsonRpcClient::connect("http://127.0.0.1:9");
+        let request = create_test_request(vec![Action::FunctionCall(Box::new(
+            FunctionCallAction {
+                method_name: "sign".to_string(),
+                args: serde_json::json!({"request": {"domain_id": 0, "path": "", "payload_v2": {"Ecdsa": hex::encode([7u8; 32])}}})
+                    .to_string()
+                    .into_bytes(),
+                gas: 0,
+                deposit: 1,
+            },
+        ))]);
+        let err = MpcSignEcdsa {}
+            .verify_result(&rpc_client, &request, &Variant::default(), b"{}")
+            .await
+            .unwrap_err();
+        assert!(!err.is::<InvalidResultError>());
+    }
+
+    #[tokio::test]
+    async fn test_ckd_verify_result() {
+        let rpc_client = JsonRpcClient::connect("http://localhost");
+        let request = create_test_request(vec![]);
+
+        let response = serde_json::json!({
+            "big_y": random_app_public_key(),
+            "big_c": random_app_public_key(),
+        });
+        assert!(MpcCkd {}
+            .verify_result(
+                &rpc_client,
+                &request,
+                &Variant::default(),
+                response.to_string().as_bytes()
+            )
+            .await
+            .is_ok());
+
+        let missing_field = serde_json::json!({ "big_y": random_app_public_key() });
+        assert!(MpcCkd {}
+            .verify_result(
+                &rpc_client,
+                &request,
+                &Variant::default(),
+                missing_field.to_string().as_bytes()
+            )
+            .await
+            .unwrap_err()
+            .is::<InvalidResultError>());
+
+        let invalid_point = serde_json::json!({
+            "big_y": random_app_public_key(),
+            "big_c": format!("{}{}", BLS12381G1_PREFIX, bs58::encode([1u8; 48]).into_string()),
+        });
+        assert!(MpcCkd {}
+            .verify_result(
+                &rpc_client,
+                &request,
+                &Variant::default(),
+                invalid_point.to_string().as_bytes()
+            )
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_find_yield_resume_blocks() {
+        let yield_receipt = CryptoHash::hash_bytes(b"yield");
+        let resume_receipt = CryptoHash::hash_bytes(b"resume");
+        let refund_receipt = CryptoHash::hash_bytes(b"refund");
+        let yield_block = CryptoHash::hash_bytes(b"yield block");
+        let resume_block = CryptoHash::hash_bytes(b"resume block");
+        let outcome = create_test_final_outcome(
+            create_test_outcome(
+                CryptoHash::new(),
+                CryptoHash::new(),
+                1,
+                ExecutionStatusView::SuccessReceiptId(yield_receipt),
+            ),
+            vec![
+                create_test_outcome(
+                    refund_receipt,
+                    resume_block,
+                    1,
+                    ExecutionStatusView::SuccessValue(vec![]),
+                ),
+                create_test_outcome(
+                    resume_receipt,
+                    resume_block,
+                    1,
+                    ExecutionStatusView::SuccessValue(vec![]),
+                ),
+                create_test_outcome(
+                    yield_receipt,
+                    yield_block,
+                    1,
+                    ExecutionStatusView::SuccessReceiptId(resume_receipt),
+                ),
+            ],
+        );
+        assert_eq!(
+            find_yield_resume_blocks(&outcome),
+            Some((yield_block, resume_block))
+        );
+
+        let mut unresumed = outcome.clone();
+        unresumed.receipts_outcome.remove(1);
+        assert_eq!(find_yield_resume_blocks(&unresumed), None);
+    }
+
+    #[test]
+    fn test_sign_variants() {
+        let variants = sign_variants(&[0, 3], &["".to_string(), "random".to_string()]);
+        let labels: Vec<&str> = variants.iter().map(|v| v.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "domain-0/empty-path",
+                "domain-0/random-path",
+                "domain-3/empty-path",
+                "domain-3/random-path"
+            ]
+        );
+
+        let paths = ["a", "random", "b", "a"].map(String::from);
+        let fixed_variants = sign_variants(&[0], &paths);
+        let labels: Vec<&str> = fixed_variants.iter().map(|v| v.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "domain-0/fixed-path-0",
+                "domain-0/random-path",
+                "domain-0/fixed-path-1"
+            ]
+        );
+        assert_eq!(request_path(&fixed_variants[2]), "b");
+
+        assert_eq!(request_path(&variants[0]), "");
+        assert_eq!(request_path(&variants[1]).len(), 32);
+        assert_ne!(request_path(&variants[1]), request_path(&variants[1]));
+    }
 }
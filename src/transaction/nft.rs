@@ -0,0 +1,553 @@
+use crate::config::Opts;
+use crate::transaction::{access_key_nonce, view_function};
+use crate::TransactionSample;
+use async_trait::async_trait;
+use near_crypto::InMemorySigner;
+use near_jsonrpc_client::JsonRpcClient;
+use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
+use near_primitives::action::FunctionCallAction;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, Transaction, TransactionV0};
+use near_primitives::types::{AccountId, Nonce};
+use rand::Rng;
+use serde_json::json;
+
+use super::signer::{sign_transaction, Signer};
+use super::{TransactionKind, Variant};
+
+/// Prefix of the ids of tokens minted by the benchmark. Only these tokens are transferred.
+const TOKEN_ID_PREFIX: &str = "tx-bench-";
+const TO_RECEIVER: &str = "to-receiver";
+const TO_SIGNER: &str = "to-signer";
+const MINT: &str = "mint";
+const BURN: &str = "burn";
+/// Number of tokens requested per `nft_tokens_for_owner` call.
+const TOKENS_PAGE_SIZE: usize = 100;
+
+pub struct NftMint {}
+
+#[async_trait]
+impl TransactionSample for NftMint {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::NftMint
+    }
+
+    fn get_name(&self) -> &str {
+        "NFT mint"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    /// Every minted token is burnt in the same run, keeping the number of tokens and their
+    /// storage bounded, unless `NftTransfer` sends tokens to the receiver without returning them.
+    /// When `NftTransfer` returns its token, one benchmark token is kept for it.
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        let supplies_transfers = opts.receiver_key.is_none()
+            && opts
+                .transaction_kind
+                .contains(&TransactionKind::NftTransfer);
+        let steps = if supplies_transfers {
+            vec![MINT]
+        } else {
+            vec![MINT, BURN]
+        };
+        steps
+            .into_iter()
+            .map(|step| Variant {
+                label: step.to_string(),
+                params: json!({}),
+            })
+            .collect()
+    }
+
+    async fn prepare(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        let contract_id = nft_contract_id(opts)?;
+        // The token kept for transfers goes back and forth, it may be owned by the receiver.
+        let kept = if keeps_transfer_token(opts) {
+            let at_receiver = find_token(rpc_client, contract_id, &opts.receiver_id).await?;
+            usize::from(at_receiver.is_none())
+        } else {
+            0
+        };
+        if variant.label != BURN {
+            // Mints the token kept for transfers along with the first token.
+            let spare = kept > 0
+                && find_token(rpc_client, contract_id, &opts.signer_id)
+                    .await?
+                    .is_none();
+            return Ok(Variant {
+                label: variant.label.clone(),
+                params: json!({ "spare": spare }),
+            });
+        }
+        let tokens = find_tokens(rpc_client, contract_id, &opts.signer_id, kept + 1).await?;
+        let token_id = tokens.get(kept).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} owns no token left to burn, {} kept for nft-transfer",
+                opts.signer_id,
+                kept
+            )
+        })?;
+        Ok(Variant {
+            label: variant.label.clone(),
+            params: json!({ "token_id": token_id }),
+        })
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let actions = if variant.label == BURN {
+            vec![FunctionCallAction {
+                method_name: opts.nft_burn_method.clone(),
+                args: json!({ "token_id": variant.params["token_id"] })
+                    .to_string()
+                    .into_bytes(),
+                gas: variant.function_call_gas(100_000_000_000_000), // 100 TeraGas
+                deposit: variant.function_call_deposit(1),
+            }]
+        } else {
+            let count = if variant.params["spare"].as_bool() == Some(true) {
+                2
+            } else {
+                1
+            };
+            (0..count)
+                .map(|_| {
+                    let mut random_bytes = [0u8; 16];
+                    rand::thread_rng().fill(&mut random_bytes);
+                    let token_id = format!("{}{}", TOKEN_ID_PREFIX, hex::encode(random_bytes));
+                    FunctionCallAction {
+                        method_name: "nft_mint".to_string(),
+                        args: json!({
+                            "token_id": token_id,
+                            "token_owner_id": signer.account_id(),
+                            "token_metadata": { "title": token_id },
+                        })
+                        .to_string()
+                        .into_bytes(),
+                        gas: variant.function_calls_gas(100_000_000_000_000, count), // 100 TeraGas
+                        // 0.01 NEAR, covers the token storage
+                        deposit: variant.function_call_deposit(10_000_000_000_000_000_000_000),
+                    }
+                })
+                .collect()
+        };
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id: opts.nft_contract_id.expect("checked in prepare"),
+            block_hash,
+            actions: actions
+                .into_iter()
+                .map(|action| Action::FunctionCall(Box::new(action)))
+                .collect(),
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+}
+
+pub struct NftTransfer {}
+
+#[async_trait]
+impl TransactionSample for NftTransfer {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::NftTransfer
+    }
+
+    fn get_name(&self) -> &str {
+        "NFT transfer"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    /// Tokens are transferred back to the signer only if the receiver key is known, otherwise
+    /// new tokens have to be minted with `NftMint` to keep the sample running.
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        let directions = match opts.receiver_key {
+            Some(_) => vec![TO_RECEIVER, TO_SIGNER],
+            None => vec![TO_RECEIVER],
+        };
+        directions
+            .into_iter()
+            .map(|direction| Variant {
+                label: direction.to_string(),
+                params: json!({}),
+            })
+            .collect()
+    }
+
+    async fn prepare(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        let contract_id = nft_contract_id(opts)?;
+        let (owner_id, nonce) = if variant.label == TO_SIGNER {
+            let signer = receiver_signer(opts).expect("variant requires the receiver key");
+            let nonce = access_key_nonce(rpc_client, &signer).await?;
+            (&opts.receiver_id, Some(nonce))
+        } else {
+            (&opts.signer_id, None)
+        };
+        let token_id = find_token(rpc_client, contract_id, owner_id)
+            .await?
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} owns no token minted by the benchmark, run nft-mint first",
+                    owner_id
+                )
+            })?;
+        Ok(Variant {
+            label: variant.label.clone(),
+            params: json!({ "token_id": token_id, "nonce": nonce }),
+        })
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        // Transfers back to the signer are signed by the receiver, with its own nonce.
+        let receiver = receiver_signer(&opts);
+        let (signer, nonce, receiver_id) = match &receiver {
+            Some(receiver) if variant.label == TO_SIGNER => (
+                receiver as &dyn Signer,
+                variant.params["nonce"].as_u64().unwrap_or_default(),
+                signer.account_id().clone(),
+            ),
+            _ => (signer, nonce, opts.receiver_id),
+        };
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id: opts.nft_contract_id.expect("checked in prepare"),
+            block_hash,
+            actions: vec![Action::FunctionCall(Box::new(FunctionCallAction {
+                method_name: "nft_transfer".to_string(),
+                args: json!({
+                    "receiver_id": receiver_id,
+                    "token_id": variant.params["token_id"],
+                })
+                .to_string()
+                .into_bytes(),
+                gas: variant.function_call_gas(100_000_000_000_000), // 100 TeraGas
+                deposit: variant.function_call_deposit(1),
+            }))],
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+}
+
+fn nft_contract_id(opts: &Opts) -> anyhow::Result<&AccountId> {
+    opts.nft_contract_id
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("NFT_CONTRACT_ID is required by NFT transactions"))
+}
+
+/// Whether `NftTransfer` sends a token back and forth, which `NftMint` must not burn.
+fn keeps_transfer_token(opts: &Opts) -> bool {
+    opts.receiver_key.is_some()
+        && opts
+            .transaction_kind
+            .contains(&TransactionKind::NftTransfer)
+}
+
+fn receiver_signer(opts: &Opts) -> Option<InMemorySigner> {
+    let key = opts.receiver_key.clone()?;
+    Some(InMemorySigner::from_secret_key(
+        opts.receiver_id.clone(),
+        key.0,
+    ))
+}
+
+/// Returns the id of a token minted by the benchmark and owned by `owner_id`.
+async fn find_token(
+    rpc_client: &JsonRpcClient,
+    contract_id: &AccountId,
+    owner_id: &AccountId,
+) -> anyhow::Result<Option<String>> {
+    Ok(find_tokens(rpc_client, contract_id, owner_id, 1)
+        .await?
+        .pop())
+}
+
+/// Returns the ids of up to `limit` tokens minted by the benchmark and owned by `owner_id`,
+/// going through all the tokens of the owner with the NEP-181 enumeration of the contract.
+async fn find_tokens(
+    rpc_client: &JsonRpcClient,
+    contract_id: &AccountId,
+    owner_id: &AccountId,
+    limit: usize,
+) -> anyhow::Result<Vec<String>> {
+    let mut found = Vec::new();
+    let mut from_index = 0;
+    loop {
+        let result = view_function(
+            rpc_client,
+            contract_id,
+            "nft_tokens_for_owner",
+            json!({
+                "account_id": owner_id,
+                "from_index": from_index.to_string(),
+                "limit": TOKENS_PAGE_SIZE,
+            }),
+        )
+        .await?;
+        let tokens: serde_json::Value = serde_json::from_slice(&result)?;
+        found.extend(benchmark_tokens(&tokens));
+        let count = tokens.as_array().map_or(0, Vec::len);
+        if found.len() >= limit || count < TOKENS_PAGE_SIZE {
+            found.truncate(limit);
+            return Ok(found);
+        }
+        from_index += count;
+    }
+}
+
+fn benchmark_tokens(tokens: &serde_json::Value) -> Vec<String> {
+    tokens
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|token| token["token_id"].as_str())
+        .filter(|token_id| token_id.starts_with(TOKEN_ID_PREFIX))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use near_primitives::types::FunctionArgs;
+    use tokio::net::TcpListener;
+
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use near_crypto::{KeyType, SecretKey};
+
+    use crate::config::Secret;
+    use crate::metrics::{create_registry_and_metrics, Labels};
+    use crate::transaction::nonces::Nonces;
+    use crate::transaction::test_utils::{
+        create_test_run_opts, create_test_tx_result, start_test_rpc,
+    };
+
+    use super::*;
+
+    /// Starts an RPC answering `nft_tokens_for_owner` with `count` tokens, the last one minted by
+    /// the benchmark, returning its URL.
+    async fn start_nft_rpc(count: usize) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                let args: FunctionArgs =
+                    serde_json::from_value(body["params"]["args_base64"].clone()).unwrap();
+                let args: serde_json::Value = serde_json::from_slice(&args).unwrap();
+                let from_index: usize = args["from_index"].as_str().unwrap().parse().unwrap();
+                let limit = args["limit"].as_u64().unwrap() as usize;
+                let tokens: Vec<serde_json::Value> = (from_index..count.min(from_index + limit))
+                    .map(|i| match i + 1 == count {
+                        true => json!({ "token_id": format!("{}last", TOKEN_ID_PREFIX) }),
+                        false => json!({ "token_id": i.to_string() }),
+                    })
+                    .collect();
+                Json(json!({
+                    "jsonrpc": "2.0",
+                    "id": body["id"],
+                    "result": {
+                        "result": serde_json::to_vec(&tokens).unwrap(),
+                        "logs": [],
+                        "block_height": 1,
+                        "block_hash": CryptoHash::new().to_string(),
+                    },
+                }))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_find_token_paginates() {
+        let contract_id: AccountId = "nft.near".parse().unwrap();
+        let owner_id: AccountId = "cat.near".parse().unwrap();
+        for count in [1, TOKENS_PAGE_SIZE, 2 * TOKENS_PAGE_SIZE + 1] {
+            let rpc_client = JsonRpcClient::connect(start_nft_rpc(count).await);
+            assert_eq!(
+                find_token(&rpc_client, &contract_id, &owner_id)
+                    .await
+                    .unwrap(),
+                Some(format!("{}last", TOKEN_ID_PREFIX))
+            );
+        }
+        let rpc_client = JsonRpcClient::connect(start_nft_rpc(0).await);
+        assert_eq!(
+            find_token(&rpc_client, &contract_id, &owner_id)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mint_variants() {
+        let labels = |opts: &Opts| -> Vec<String> {
+            NftMint {}
+                .variants(opts)
+                .into_iter()
+                .map(|variant| variant.label)
+                .collect()
+        };
+        let opts = create_test_run_opts();
+        assert_eq!(labels(&opts), vec![MINT, BURN]);
+
+        // Minted tokens are needed by transfers to the receiver, which are not returned.
+        let opts = Opts {
+            receiver_key: None,
+            transaction_kind: vec![TransactionKind::NftMint, TransactionKind::NftTransfer],
+            ..opts
+        };
+        assert_eq!(labels(&opts), vec![MINT]);
+    }
+
+    #[tokio::test]
+    async fn test_mint_burn_and_transfer() {
+        // Owners of the tokens of the contract, updated by the transactions sent to it.
+        let owners = Arc::new(Mutex::new(BTreeMap::<String, AccountId>::new()));
+        let contract = owners.clone();
+        let url = start_test_rpc(move |method, params| {
+            let mut owners = contract.lock().unwrap();
+            if method == "query" && params["request_type"] == "view_access_key" {
+                return Ok(json!({
+                    "nonce": 0,
+                    "permission": "FullAccess",
+                    "block_height": 1,
+                    "block_hash": CryptoHash::new().to_string(),
+                }));
+            }
+            if method == "query" {
+                let args: FunctionArgs =
+                    serde_json::from_value(params["args_base64"].clone()).unwrap();
+                let args: serde_json::Value = serde_json::from_slice(&args).unwrap();
+                let tokens: Vec<_> = owners
+                    .iter()
+                    .filter(|(_, owner)| args["account_id"] == owner.as_str())
+                    .map(|(token_id, _)| json!({ "token_id": token_id }))
+                    .collect();
+                return Ok(json!({
+                    "result": serde_json::to_vec(&tokens).unwrap(),
+                    "logs": [],
+                    "block_height": 1,
+                    "block_hash": CryptoHash::new().to_string(),
+                }));
+            }
+            let request: RpcSendTransactionRequest =
+                serde_json::from_value(params.clone()).unwrap();
+            let transaction = &request.signed_transaction.transaction;
+            for action in transaction.actions() {
+                let Action::FunctionCall(call) = action else {
+                    panic!("expected a function call");
+                };
+                let args: serde_json::Value = serde_json::from_slice(&call.args).unwrap();
+                let token_id = args["token_id"].as_str().unwrap().to_string();
+                if call.method_name == "nft_mint" {
+                    let owner = args["token_owner_id"].as_str().unwrap().parse().unwrap();
+                    owners.insert(token_id, owner);
+                    continue;
+                }
+                if owners.get(&token_id) != Some(transaction.signer_id()) {
+                    return Err(json!({"code": -32000, "message": "not the token owner"}));
+                }
+                match call.method_name.as_str() {
+                    "nft_burn" => owners.remove(&token_id),
+                    _ => owners.insert(
+                        token_id,
+                        args["receiver_id"].as_str().unwrap().parse().unwrap(),
+                    ),
+                };
+            }
+            Ok(create_test_tx_result(&request, vec![]))
+        })
+        .await;
+
+        let opts = Opts {
+            nft_contract_id: Some("nft.near".parse().unwrap()),
+            receiver_key: Some(Secret(SecretKey::from_random(KeyType::ED25519))),
+            transaction_kind: vec![TransactionKind::NftMint, TransactionKind::NftTransfer],
+            ..create_test_run_opts()
+        };
+        let rpc_client = JsonRpcClient::connect(url);
+        let (_registry, metrics) = create_registry_and_metrics();
+        let nonces = Nonces::default();
+        let samples: [&dyn TransactionSample; 2] = [&NftMint {}, &NftTransfer {}];
+        for _ in 0..3 {
+            for sample in samples {
+                for variant in sample.variants(&opts) {
+                    let labels = Labels::new(
+                        sample.kind().to_string(),
+                        variant.label.clone(),
+                        "localnet".to_string(),
+                        "eu".to_string(),
+                    );
+                    sample
+                        .execute(
+                            &rpc_client,
+                            opts.clone(),
+                            &variant,
+                            &metrics,
+                            &labels,
+                            &nonces,
+                            CryptoHash::new(),
+                        )
+                        .await
+                        .unwrap();
+                }
+            }
+            // Minted tokens are burnt, except the one transferred back and forth.
+            let owners = owners.lock().unwrap();
+            assert_eq!(owners.len(), 1);
+            assert_eq!(owners.values().next(), Some(&opts.signer_id));
+        }
+    }
+
+    #[test]
+    fn test_benchmark_tokens() {
+        let tokens = json!([
+            {"token_id": "1", "owner_id": "cat.near"},
+            {"token_id": "tx-bench-ab12", "owner_id": "cat.near"},
+        ]);
+        assert_eq!(benchmark_tokens(&tokens), vec!["tx-bench-ab12".to_string()]);
+        assert!(benchmark_tokens(&json!([{"token_id": "1"}])).is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+//! Nonces of the signer key, shared by all the transactions sent with it.
+
+use near_primitives::types::Nonce;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// How long the next transaction waits for the one being sent, unless it completes earlier, so
+/// that transactions reach the RPC node in nonce order.
+pub(crate) const SEND_ORDER_DELAY: Duration = Duration::from_millis(10);
+
+/// Hands out the nonces of the signer key. A transaction reserves its nonce once it is
+/// prepared, right before it is signed, and keeps the key locked while it is sent. Nonces thus
+/// increase in the order transactions are sent, whichever kind finished preparing first.
+#[derive(Debug, Default)]
+pub struct Nonces {
+    /// Nonce passed to the next transaction, which signs it incremented by one.
+    next: Mutex<Nonce>,
+}
+
+impl Nonces {
+    /// Skips nonces up to `nonce`, the nonce of the access key on chain, e.g. used by another
+    /// client of the key.
+    pub async fn observe(&self, nonce: Nonce) {
+        let mut next = self.next.lock().await;
+        *next = (*next).max(nonce);
+    }
+
+    /// Locks the key until the returned guard is dropped, see `send_in_order`, and returns the
+    /// reserved nonce.
+    pub async fn reserve(&self) -> (Nonce, MutexGuard<'_, Nonce>) {
+        self.reserve_many(1).await
+    }
+
+    /// Reserves `count` consecutive nonces, for transactions sent together, returning the first.
+    pub async fn reserve_many(&self, count: usize) -> (Nonce, MutexGuard<'_, Nonce>) {
+        let mut next = self.next.lock().await;
+        let nonce = *next;
+        *next += count as Nonce;
+        (nonce, next)
+    }
+}
+
+/// Awaits `send`, keeping the key locked by `guard` until `send` completes or had
+/// `SEND_ORDER_DELAY` to reach the RPC node.
+pub(crate) async fn send_in_order<T>(
+    send: impl Future<Output = T>,
+    guard: MutexGuard<'_, Nonce>,
+) -> T {
+    tokio::pin!(send);
+    tokio::select! {
+        output = &mut send => output,
+        _ = tokio::time::sleep(SEND_ORDER_DELAY) => {
+            drop(guard);
+            send.await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::{sleep, Instant};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reserve_after_observe() {
+        let nonces = Nonces::default();
+        assert_eq!(nonces.reserve().await.0, 0);
+        nonces.observe(10).await;
+        assert_eq!(nonces.reserve().await.0, 10);
+        nonces.observe(5).await;
+        assert_eq!(nonces.reserve().await.0, 11);
+        assert_eq!(nonces.reserve_many(3).await.0, 12);
+        assert_eq!(nonces.reserve().await.0, 15);
+    }
+
+    #[tokio::test]
+    async fn test_send_in_order_holds_key() {
+        let nonces = Nonces::default();
+        let (nonce, guard) = nonces.reserve().await;
+        assert_eq!(nonce, 0);
+        let started = Instant::now();
+        let send = send_in_order(sleep(Duration::from_millis(500)), guard);
+        let next = async {
+            let (nonce, _guard) = nonces.reserve().await;
+            (nonce, started.elapsed())
+        };
+        let (_, (nonce, elapsed)) = tokio::join!(send, next);
+        // The slow send released the key after the delay, long before it completed.
+        assert_eq!(nonce, 1);
+        assert!(elapsed >= SEND_ORDER_DELAY);
+        assert!(elapsed < Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::config::Opts;
+use crate::transaction::access_key_nonce;
+use crate::TransactionSample;
+use async_trait::async_trait;
+use near_crypto::InMemorySigner;
+use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
+use near_primitives::action::delegate::{DelegateAction, SignedDelegateAction};
+use near_primitives::action::TransferAction;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, Transaction, TransactionV0};
+use near_primitives::types::{BlockHeight, BlockReference, Nonce};
+use serde_json::json;
+use std::sync::Mutex;
+
+use super::signer::{sign_transaction, Signer};
+use super::{TransactionKind, Variant};
+
+/// Number of blocks during which a delegate action can be relayed.
+const DELEGATE_ACTION_TTL: BlockHeight = 120;
+
+/// Same transfer as `TokenTransferDefault`, signed by the signer as a NEP-366 delegate action
+/// and submitted by the relayer.
+#[derive(Default)]
+pub struct RelayedTokenTransfer {
+    /// Last nonce used by the relayer, in case its access key is queried before it is updated.
+    relayer_nonce: Mutex<Nonce>,
+}
+
+#[async_trait]
+impl TransactionSample for RelayedTokenTransfer {
+    fn kind(&self) -> TransactionKind {
+        TransactionKind::RelayedTokenTransfer
+    }
+
+    fn get_name(&self) -> &str {
+        "NEAR transfer relayed as a delegate action"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    /// The delegate action uses the signer nonce once the relayer transaction is executed, a
+    /// transaction sent meanwhile with a higher nonce would invalidate it.
+    fn locks_key_until_executed(&self) -> bool {
+        true
+    }
+
+    async fn prepare(
+        &self,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        let relayer = relayer_signer(opts).ok_or_else(|| {
+            anyhow::anyhow!("RELAYER_ID and RELAYER_KEY are required by relayed transactions")
+        })?;
+        let nonce = access_key_nonce(rpc_client, &relayer).await?;
+        let nonce = {
+            let mut relayer_nonce = self
+                .relayer_nonce
+                .lock()
+                .expect("relayer nonce lock poisoned");
+            *relayer_nonce = nonce.max(*relayer_nonce) + 1;
+            *relayer_nonce
+        };
+        let block = rpc_client
+            .call(methods::block::RpcBlockRequest {
+                block_reference: BlockReference::latest(),
+            })
+            .await?;
+        Ok(Variant {
+            label: variant.label.clone(),
+            params: json!({
+                "relayer_nonce": nonce,
+                "max_block_height": block.header.height + DELEGATE_ACTION_TTL,
+            }),
+        })
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
+        opts: Opts,
+        variant: &Variant,
+        nonce: Nonce,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let delegate_action = DelegateAction {
+            sender_id: signer.account_id().clone(),
+            receiver_id: opts.receiver_id.clone(),
+            actions: vec![Action::Transfer(TransferAction { deposit: 1 })
+                .try_into()
+                .expect("transfer is not a delegate action")],
+            nonce: nonce + 1,
+            max_block_height: variant.params["max_block_height"]
+                .as_u64()
+                .unwrap_or_default(),
+            public_key: signer.public_key(),
+        };
+        let signature = signer
+            .sign(delegate_action.get_nep461_hash().as_ref())
+            .await?;
+        let relayer = relayer_signer(&opts).expect("checked in prepare");
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: relayer.account_id.clone(),
+            public_key: relayer.public_key.clone(),
+            nonce: variant.params["relayer_nonce"].as_u64().unwrap_or_default(),
+            receiver_id: signer.account_id().clone(),
+            block_hash,
+            actions: vec![SignedDelegateAction {
+                delegate_action,
+                signature,
+            }
+            .into()],
+        });
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(&relayer, transaction).await?,
+            wait_until: Default::default(),
+        })
+    }
+}
+
+fn relayer_signer(opts: &Opts) -> Option<InMemorySigner> {
+    Some(InMemorySigner::from_secret_key(
+        opts.relayer_id.clone()?,
+        opts.relayer_key.clone()?.0,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::{routing::post, Json, Router};
+    use near_crypto::{KeyType, SecretKey};
+    use tokio::net::TcpListener;
+    use tokio::time::sleep;
+
+    use crate::config::Secret;
+    use crate::metrics::{create_registry_and_metrics, Labels};
+    use crate::transaction::nonces::Nonces;
+    use crate::transaction::test_utils::{
+        create_test_block, create_test_run_opts, create_test_tx_result,
+    };
+    use crate::transaction::token_transfer_default::TokenTransferDefault;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delegate_action_is_signed_by_signer() {
+        let mut opts = create_test_run_opts();
+        opts.relayer_id = Some("relayer.near".parse().unwrap());
+        opts.relayer_key = Some(Secret(SecretKey::from_random(KeyType::ED25519)));
+        let signer = InMemorySigner::from_secret_key(opts.signer_id.clone(), opts.signer_key());
+        let variant = Variant {
+            label: String::new(),
+            params: json!({ "relayer_nonce": 8, "max_block_height": 100 }),
+        };
+        let request = RelayedTokenTransfer::default()
+            .get_transaction_request(&signer, opts, &variant, 4, CryptoHash::new())
+            .await
+            .unwrap();
+        let transaction = &request.signed_transaction.transaction;
+        assert_eq!(transaction.signer_id().as_str(), "relayer.near");
+        assert_eq!(transaction.receiver_id().as_str(), "cat.near");
+        assert_eq!(transaction.nonce(), 8);
+        let [Action::Delegate(signed_delegate_action)] = transaction.actions() else {
+            panic!("expected a single delegate action");
+        };
+        assert!(signed_delegate_action.verify());
+        assert_eq!(signed_delegate_action.delegate_action.nonce, 5);
+        assert_eq!(signed_delegate_action.delegate_action.max_block_height, 100);
+    }
+
+    #[tokio::test]
+    async fn test_key_locked_until_relayed_transaction_executed() {
+        // Transactions received by the RPC, which executes relayed ones slowly.
+        let received = Arc::new(Mutex::new(vec![]));
+        let events = received.clone();
+        let app = Router::new().route(
+            "/",
+            post(move |Json(body): Json<serde_json::Value>| {
+                let events = events.clone();
+                async move {
+                    let result = match body["method"].as_str().unwrap() {
+                        "query" => json!({
+                            "nonce": 0,
+                            "permission": "FullAccess",
+                            "block_height": 1,
+                            "block_hash": CryptoHash::new().to_string(),
+                        }),
+                        "block" => {
+                            serde_json::to_value(create_test_block(1, CryptoHash::new())).unwrap()
+                        }
+                        _ => {
+                            let request: RpcSendTransactionRequest =
+                                serde_json::from_value(body["params"].clone()).unwrap();
+                            let transaction = &request.signed_transaction.transaction;
+                            let relayed = transaction.signer_id().as_str() == "relayer.near";
+                            let event = if relayed { "relayed" } else { "direct" };
+                            events.lock().unwrap().push(format!("{} sent", event));
+                            if relayed {
+                                sleep(Duration::from_millis(200)).await;
+                                events.lock().unwrap().push("relayed executed".to_string());
+                            }
+                            create_test_tx_result(&request, vec![])
+                        }
+                    };
+                    Json(json!({"jsonrpc": "2.0", "id": body["id"], "result": result}))
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (_registry, metrics) = create_registry_and_metrics();
+        let labels = Labels::new(
+            "relayed-token-transfer".to_string(),
+            String::new(),
+            "localnet".to_string(),
+            "eu".to_string(),
+        );
+        let mut opts = create_test_run_opts();
+        opts.relayer_id = Some("relayer.near".parse().unwrap());
+        opts.relayer_key = Some(Secret(SecretKey::from_random(KeyType::ED25519)));
+        let rpc_client = JsonRpcClient::connect(url);
+        let nonces = Nonces::default();
+        let sample = RelayedTokenTransfer::default();
+        let variant = Variant::default();
+        let relayed = sample.execute(
+            &rpc_client,
+            opts.clone(),
+            &variant,
+            &metrics,
+            &labels,
+            &nonces,
+            CryptoHash::new(),
+        );
+        // Reserves the next nonce while the relayed transaction is being executed.
+        let direct = async {
+            sleep(Duration::from_millis(100)).await;
+            TokenTransferDefault {}
+                .execute(
+                    &rpc_client,
+                    opts.clone(),
+                    &variant,
+                    &metrics,
+                    &labels,
+                    &nonces,
+                    CryptoHash::new(),
+                )
+                .await
+        };
+        let (relayed, direct) = tokio::join!(relayed, direct);
+        relayed.unwrap();
+        direct.unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            ["relayed sent", "relayed executed", "direct sent"]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Retries of RPC requests failing with transient errors.
+
+use near_jsonrpc_client::errors::{
+    JsonRpcError, JsonRpcServerError, JsonRpcServerResponseStatusError,
+};
+use near_jsonrpc_primitives::types::transactions::RpcTransactionError;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::config::{Opts, RetryableError};
+use crate::metrics::{Labels, Metrics};
+
+#[derive(Debug, Clone)]
+pub(crate) struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+    retryable: Vec<RetryableError>,
+}
+
+impl RetryPolicy {
+    pub fn from_opts(opts: &Opts) -> Self {
+        Self {
+            max_attempts: opts.retry_max_attempts.max(1),
+            initial_backoff: opts.retry_initial_backoff,
+            max_backoff: opts.retry_max_backoff,
+            jitter: opts.retry_jitter.clamp(0.0, 1.0),
+            retryable: opts.retryable_errors.clone(),
+        }
+    }
+
+    /// Sends the request built by `call` until it succeeds, fails with an error that is not
+    /// retryable, or `max_attempts` is reached. Every retry is counted in the `retries` metric.
+    pub async fn call<R, F, Fut>(
+        &self,
+        metrics: &Metrics,
+        labels: &Labels,
+        mut call: F,
+    ) -> Result<R, JsonRpcError<RpcTransactionError>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R, JsonRpcError<RpcTransactionError>>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(err) if attempt < self.max_attempts && self.is_retryable(&err) => {
+                    let backoff = self.backoff(attempt);
+                    warn!(
+                        "attempt {} of {} failed, retrying in {:?}: {}",
+                        attempt, self.max_attempts, backoff, err
+                    );
+                    metrics.retries.get_or_create(labels).inc();
+                    sleep(backoff).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn is_retryable(&self, err: &JsonRpcError<RpcTransactionError>) -> bool {
+        error_class(err).is_some_and(|class| self.retryable.contains(&class))
+    }
+
+    /// Returns the delay before retrying after `attempt`, exponential up to `max_backoff` and
+    /// varied randomly by up to `jitter` in both directions.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+        if self.jitter == 0.0 {
+            return backoff;
+        }
+        backoff.mul_f64(1.0 + rand::thread_rng().gen_range(-self.jitter..=self.jitter))
+    }
+}
+
+fn error_class(err: &JsonRpcError<RpcTransactionError>) -> Option<RetryableError> {
+    match err {
+        JsonRpcError::TransportError(_) => Some(RetryableError::Transport),
+        JsonRpcError::ServerError(err) => match err {
+            JsonRpcServerError::ResponseStatusError(
+                JsonRpcServerResponseStatusError::TooManyRequests,
+            ) => Some(RetryableError::RateLimited),
+            JsonRpcServerError::ResponseStatusError(
+                JsonRpcServerResponseStatusError::Unexpected { status },
+            ) if status.is_server_error() => Some(RetryableError::Unavailable),
+            JsonRpcServerError::InternalError { .. }
+            | JsonRpcServerError::HandlerError(RpcTransactionError::InternalError { .. }) => {
+                Some(RetryableError::Internal)
+            }
+            JsonRpcServerError::HandlerError(
+                RpcTransactionError::DoesNotTrackShard | RpcTransactionError::RequestRouted { .. },
+            ) => Some(RetryableError::Routing),
+            JsonRpcServerError::HandlerError(RpcTransactionError::UnknownTransaction {
+                ..
+            }) => Some(RetryableError::UnknownTransaction),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use crate::metrics::create_registry_and_metrics;
+    use crate::transaction::test_utils::create_test_run_opts;
+
+    use super::*;
+
+    fn create_test_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::from_opts(&Opts {
+            retry_max_attempts: max_attempts,
+            retryable_errors: vec![RetryableError::Internal],
+            ..create_test_run_opts()
+        })
+    }
+
+    fn internal_error() -> JsonRpcError<RpcTransactionError> {
+        JsonRpcError::ServerError(JsonRpcServerError::InternalError { info: None })
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+            jitter: 0.0,
+            retryable: vec![],
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        for _ in 0..100 {
+            let backoff = policy.backoff(1);
+            assert!(backoff >= Duration::from_millis(50) && backoff <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn test_error_class() {
+        assert_eq!(
+            error_class(&internal_error()),
+            Some(RetryableError::Internal)
+        );
+        assert_eq!(
+            error_class(&JsonRpcError::ServerError(
+                JsonRpcServerError::ResponseStatusError(
+                    JsonRpcServerResponseStatusError::TooManyRequests
+                )
+            )),
+            Some(RetryableError::RateLimited)
+        );
+        assert_eq!(
+            error_class(&JsonRpcError::ServerError(
+                JsonRpcServerError::HandlerError(RpcTransactionError::DoesNotTrackShard)
+            )),
+            Some(RetryableError::Routing)
+        );
+        assert_eq!(
+            error_class(&JsonRpcError::ServerError(
+                JsonRpcServerError::HandlerError(RpcTransactionError::TimeoutError)
+            )),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_call_retries_retryable_errors() {
+        let (_registry, metrics) = create_registry_and_metrics();
+        let labels = Labels::new(
+            "swap".to_string(),
+            String::new(),
+            "mainnet".to_string(),
+            "eu".to_string(),
+        );
+        let attempts = AtomicU32::new(0);
+        let res = create_test_policy(3)
+            .call(&metrics, &labels, || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(internal_error()),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+        assert!(res.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(metrics.retries.get_or_create(&labels).get(), 1);
+
+        let res: Result<(), _> = create_test_policy(3)
+            .call(&metrics, &labels, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(internal_error())
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 5);
+        assert_eq!(metrics.retries.get_or_create(&labels).get(), 3);
+
+        let res: Result<(), _> = create_test_policy(3)
+            .call(&metrics, &labels, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+                    RpcTransactionError::TimeoutError,
+                )))
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 6);
+    }
+}
@@ -0,0 +1,216 @@
+//! Signers of transactions and delegate actions, holding the key in memory or delegating to a
+//! remote signing service.
+
+use async_trait::async_trait;
+use near_crypto::{InMemorySigner, PublicKey, Signature};
+use near_primitives::transaction::{SignedTransaction, Transaction};
+use near_primitives::types::AccountId;
+use reqwest::header::HeaderMap;
+use serde_json::json;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::config::Opts;
+
+/// Timeout of requests to the remote signing service.
+const REMOTE_SIGNING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Signs on behalf of `account_id` with the access key `public_key`.
+#[async_trait]
+pub trait Signer: Send + Sync {
+    fn account_id(&self) -> &AccountId;
+
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs `hash`, the hash of a transaction or of a delegate action.
+    async fn sign(&self, hash: &[u8]) -> anyhow::Result<Signature>;
+}
+
+#[async_trait]
+impl Signer for InMemorySigner {
+    fn account_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    async fn sign(&self, hash: &[u8]) -> anyhow::Result<Signature> {
+        Ok(self.secret_key.sign(hash))
+    }
+}
+
+/// Signer calling an external signing service, so the key never leaves it. The service receives
+/// `{"account_id": ..., "public_key": ..., "hash": <base58>}` as a POST request and responds with
+/// `{"signature": "ed25519:..."}`.
+pub struct RemoteSigner {
+    http_client: reqwest::Client,
+    url: String,
+    headers: HeaderMap,
+    account_id: AccountId,
+    public_key: PublicKey,
+}
+
+impl RemoteSigner {
+    pub fn new(
+        url: String,
+        headers: HeaderMap,
+        account_id: AccountId,
+        public_key: PublicKey,
+    ) -> Self {
+        // Shared by all signers, so connections to the service are reused across transactions.
+        static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+        Self {
+            http_client: HTTP_CLIENT.get_or_init(reqwest::Client::new).clone(),
+            url,
+            headers,
+            account_id,
+            public_key,
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn account_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    async fn sign(&self, hash: &[u8]) -> anyhow::Result<Signature> {
+        let response: serde_json::Value = self
+            .http_client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .timeout(REMOTE_SIGNING_TIMEOUT)
+            .json(&json!({
+                "account_id": self.account_id,
+                "public_key": self.public_key,
+                "hash": bs58::encode(hash).into_string(),
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let signature: Signature = response["signature"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("signing service response has no signature"))?
+            .parse()?;
+        if !signature.verify(hash, &self.public_key) {
+            anyhow::bail!(
+                "signing service returned an invalid signature for {}",
+                self.public_key
+            );
+        }
+        Ok(signature)
+    }
+}
+
+/// Returns the signer of `account_id` using the key of the configured signer, either remote or
+/// in memory.
+pub fn signer_for(opts: &Opts, account_id: AccountId) -> Arc<dyn Signer> {
+    match (&opts.remote_signer_url, &opts.remote_signer_public_key) {
+        (Some(url), Some(public_key)) => Arc::new(RemoteSigner::new(
+            url.0.clone(),
+            opts.remote_signer_headers.clone().unwrap_or_default(),
+            account_id,
+            public_key.clone(),
+        )),
+        _ => Arc::new(InMemorySigner::from_secret_key(
+            account_id,
+            opts.signer_key(),
+        )),
+    }
+}
+
+/// Returns the signer of the configured signer account.
+pub fn signer(opts: &Opts) -> Arc<dyn Signer> {
+    signer_for(opts, opts.signer_id.clone())
+}
+
+/// Signs `transaction` with `signer`.
+pub async fn sign_transaction(
+    signer: &dyn Signer,
+    transaction: Transaction,
+) -> anyhow::Result<SignedTransaction> {
+    let (hash, _) = transaction.get_hash_and_size();
+    let signature = signer.sign(hash.as_ref()).await?;
+    Ok(SignedTransaction::new(signature, transaction))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::transaction::TransactionV0;
+    use tokio::net::TcpListener;
+
+    use crate::config::Secret;
+    use crate::transaction::test_utils::create_test_run_opts;
+
+    use super::*;
+
+    /// Starts a signing service signing with `secret_key`, returning its URL.
+    async fn start_signing_service(secret_key: SecretKey) -> String {
+        let app = Router::new().route(
+            "/sign",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                let hash = bs58::decode(body["hash"].as_str().unwrap())
+                    .into_vec()
+                    .unwrap();
+                Json(json!({ "signature": secret_key.sign(&hash) }))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/sign", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    fn create_test_transaction(signer: &dyn Signer) -> Transaction {
+        Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: 1,
+            receiver_id: "dog.near".parse().unwrap(),
+            block_hash: CryptoHash::new(),
+            actions: vec![],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer() {
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        let opts = Opts {
+            signer_key: None,
+            remote_signer_url: Some(Secret(start_signing_service(secret_key.clone()).await)),
+            remote_signer_public_key: Some(secret_key.public_key()),
+            ..create_test_run_opts()
+        };
+        let signer = signer(&opts);
+        let signed = sign_transaction(signer.as_ref(), create_test_transaction(signer.as_ref()))
+            .await
+            .unwrap();
+        assert!(signed
+            .signature
+            .verify(signed.get_hash().as_ref(), &secret_key.public_key()));
+
+        // A service signing with another key is detected.
+        let opts = Opts {
+            remote_signer_public_key: Some(SecretKey::from_random(KeyType::ED25519).public_key()),
+            ..opts
+        };
+        let signer = super::signer(&opts);
+        assert!(
+            sign_transaction(signer.as_ref(), create_test_transaction(signer.as_ref()))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,17 +1,23 @@
-use crate::config::Opts;
+use crate::config::{Opts, SwapDirection, SwapHop};
+use crate::transaction::view_function;
 use crate::TransactionSample;
 use async_trait::async_trait;
-use near_crypto::InMemorySigner;
+use near_jsonrpc_client::JsonRpcClient;
 use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
 use near_primitives::action::FunctionCallAction;
 use near_primitives::hash::CryptoHash;
 use near_primitives::transaction::{Action, Transaction, TransactionV0};
-use near_primitives::types::Nonce;
+use near_primitives::types::{AccountId, Balance, Nonce};
+use serde_json::json;
 
-use super::TransactionKind;
+use super::signer::{sign_transaction, Signer};
+use super::{InvalidResultError, TransactionKind, Variant};
 
 pub struct Swap {}
 
+const FORWARD: &str = "forward";
+const REVERSE: &str = "reverse";
+
 #[async_trait]
 impl TransactionSample for Swap {
     fn kind(&self) -> TransactionKind {
@@ -19,42 +25,371 @@ impl TransactionSample for Swap {
     }
 
     fn get_name(&self) -> &str {
-        "swap from NEAR to USDT"
+        "swap between NEAR and USDT"
+    }
+
+    fn variants(&self, opts: &Opts) -> Vec<Variant> {
+        let directions = match opts.swap_direction {
+            SwapDirection::Forward => vec![FORWARD],
+            SwapDirection::Reverse => vec![REVERSE],
+            SwapDirection::Alternate => vec![FORWARD, REVERSE],
+        };
+        directions
+            .into_iter()
+            .map(|direction| Variant {
+                label: direction.to_string(),
+                params: json!({}),
+            })
+            .collect()
     }
 
-    fn get_transaction_request(
+    async fn prepare(
         &self,
-        signer: InMemorySigner,
+        rpc_client: &JsonRpcClient,
+        opts: &Opts,
+        variant: &Variant,
+    ) -> anyhow::Result<Variant> {
+        let route = swap_route(opts);
+        let token = &route.last().expect("swap route is never empty").token_out;
+        // The reverse swap sells what a forward swap of `swap_amount` buys at the current price.
+        let mut amount_in = expected_return(
+            rpc_client,
+            opts,
+            &opts.wrap_near_id,
+            opts.swap_amount,
+            &route,
+        )
+        .await?;
+        let mut expected = amount_in;
+        if variant.label == REVERSE {
+            let reverse_route = reverse_route(&opts.wrap_near_id, &route);
+            expected = expected_return(rpc_client, opts, token, amount_in, &reverse_route).await?;
+        } else {
+            amount_in = opts.swap_amount;
+        }
+        Ok(Variant {
+            label: variant.label.clone(),
+            params: json!({
+                "amount_in": amount_in.to_string(),
+                "min_amount_out": with_slippage(expected, opts.swap_slippage).to_string(),
+            }),
+        })
+    }
+
+    async fn get_transaction_request(
+        &self,
+        signer: &dyn Signer,
         opts: Opts,
+        variant: &Variant,
         nonce: Nonce,
         block_hash: CryptoHash,
-    ) -> RpcSendTransactionRequest {
-        let msg =  format!("{{\"actions\":[{{\"pool_id\":{},\"token_in\":\"{}\",\"token_out\":\"{}\",\"amount_in\":\"1000000000000000000000\",\"min_amount_out\":\"1\"}}]}}", opts.pool_id, opts.wrap_near_id, opts.ft_account_id);
-        let transaction = Transaction::V0 (TransactionV0  {
-            signer_id: signer.account_id.clone(),
-            public_key: signer.public_key.clone(),
-            nonce: nonce + 1,
-            receiver_id: opts.wrap_near_id,
-            block_hash,
-            actions: vec![
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
+        let amount_in = balance_param(variant, "amount_in").unwrap_or(opts.swap_amount);
+        let min_amount_out = balance_param(variant, "min_amount_out").unwrap_or(1);
+        let route = swap_route(&opts);
+        let (receiver_id, actions) = if variant.label == REVERSE {
+            let token = route
+                .last()
+                .expect("swap route is never empty")
+                .token_out
+                .clone();
+            let mut msg = swap_msg(
+                &reverse_route(&opts.wrap_near_id, &route),
+                &token,
+                amount_in,
+                min_amount_out,
+            );
+            // Makes the exchange withdraw the received wNEAR and send NEAR to the signer.
+            msg["skip_unwrap_near"] = json!(false);
+            let actions = vec![Action::FunctionCall(Box::new(FunctionCallAction {
+                method_name: "ft_transfer_call".to_string(),
+                args: json!({
+                    "msg": msg.to_string(),
+                    "amount": amount_in.to_string(),
+                    "receiver_id": opts.exchange_id,
+                })
+                .to_string()
+                .into_bytes(),
+                gas: variant.function_call_gas(200_000_000_000_000), // 200 TeraGas
+                deposit: variant.function_call_deposit(1),
+            }))];
+            (token, actions)
+        } else {
+            let msg = swap_msg(&route, &opts.wrap_near_id, amount_in, min_amount_out);
+            let actions = vec![
                 Action::FunctionCall(Box::new(FunctionCallAction {
                     method_name: "near_deposit".to_string(),
-                    args: serde_json::json!({}).to_string().into_bytes(),
-                    gas: 100_000_000_000_000, // 100 TeraGas
-                    deposit: 1_000_000_000_000_000_000_000, // 0.001 NEAR
+                    args: json!({}).to_string().into_bytes(),
+                    gas: variant.function_calls_gas(100_000_000_000_000, 2), // 100 TeraGas
+                    deposit: amount_in,
                 })),
                 Action::FunctionCall(Box::new(FunctionCallAction {
                     method_name: "ft_transfer_call".to_string(),
-                    args: serde_json::json!(
-                        {"msg": msg,"amount": "1000000000000000000000","receiver_id": opts.exchange_id}).to_string().into_bytes(),
-                    gas: 100_000_000_000_000, // 100 TeraGas
-                    deposit: 1,
+                    args: json!({
+                        "msg": msg.to_string(),
+                        "amount": amount_in.to_string(),
+                        "receiver_id": opts.exchange_id,
+                    })
+                    .to_string()
+                    .into_bytes(),
+                    gas: variant.function_calls_gas(100_000_000_000_000, 2), // 100 TeraGas
+                    deposit: variant.function_call_deposit(1),
                 })),
-            ],
+            ];
+            (opts.wrap_near_id, actions)
+        };
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
+            nonce: nonce + 1,
+            receiver_id,
+            block_hash,
+            actions,
         });
-        RpcSendTransactionRequest {
-            signed_transaction: transaction.sign(&signer.into()),
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
             wait_until: Default::default(),
+        })
+    }
+
+    /// `ft_transfer_call` refunds the tokens without failing if the swap fails, so the amount it
+    /// returns, the part of the tokens used by the exchange, is checked instead of the
+    /// transaction status. Unlike balances, it is not affected by other transactions of the
+    /// signer running concurrently.
+    async fn verify_result(
+        &self,
+        _rpc_client: &JsonRpcClient,
+        request: &RpcSendTransactionRequest,
+        _variant: &Variant,
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        let used: Balance = serde_json::from_slice::<String>(value)
+            .map_err(|err| InvalidResultError(format!("not an amount: {}", err)))?
+            .parse()
+            .map_err(|err| InvalidResultError(format!("not an amount: {}", err)))?;
+        if used == 0 {
+            let transaction = &request.signed_transaction.transaction;
+            return Err(InvalidResultError(format!(
+                "swap of {} to {} failed, the exchange refunded all the tokens",
+                transaction.receiver_id(),
+                swap_msg_token_out(transaction.actions())?
+            ))
+            .into());
         }
+        Ok(())
+    }
+}
+
+fn balance_param(variant: &Variant, name: &str) -> Option<Balance> {
+    variant.params[name].as_str()?.parse().ok()
+}
+
+/// Returns the configured swap route, or a single hop through `pool_id` to `ft_account_id`.
+fn swap_route(opts: &Opts) -> Vec<SwapHop> {
+    if opts.swap_route.is_empty() {
+        vec![SwapHop {
+            pool_id: opts.pool_id,
+            token_out: opts.ft_account_id.clone(),
+        }]
+    } else {
+        opts.swap_route.clone()
+    }
+}
+
+/// Returns the route swapping the last token of `route` back to `token_in`, through the same pools.
+fn reverse_route(token_in: &AccountId, route: &[SwapHop]) -> Vec<SwapHop> {
+    let tokens: Vec<&AccountId> = std::iter::once(token_in)
+        .chain(route.iter().map(|hop| &hop.token_out))
+        .collect();
+    route
+        .iter()
+        .zip(tokens)
+        .rev()
+        .map(|(hop, token_out)| SwapHop {
+            pool_id: hop.pool_id,
+            token_out: token_out.clone(),
+        })
+        .collect()
+}
+
+/// Queries the exchange for the amount received at the end of the route.
+async fn expected_return(
+    rpc_client: &JsonRpcClient,
+    opts: &Opts,
+    token_in: &AccountId,
+    amount_in: Balance,
+    route: &[SwapHop],
+) -> anyhow::Result<Balance> {
+    let mut amount = amount_in;
+    let mut token_in = token_in;
+    for hop in route {
+        let result = view_function(
+            rpc_client,
+            &opts.exchange_id,
+            "get_return",
+            json!({
+                "pool_id": hop.pool_id,
+                "token_in": token_in,
+                "amount_in": amount.to_string(),
+                "token_out": hop.token_out,
+            }),
+        )
+        .await?;
+        amount = serde_json::from_slice::<String>(&result)?.parse()?;
+        token_in = &hop.token_out;
+    }
+    Ok(amount)
+}
+
+/// Returns the minimum amount accepted out of a swap expected to return `amount`.
+fn with_slippage(amount: Balance, slippage: f64) -> Balance {
+    let slippage_bps = (slippage.clamp(0.0, 1.0) * 10_000.0).round() as u128;
+    (amount - amount.saturating_mul(slippage_bps) / 10_000).max(1)
+}
+
+/// Builds the `ft_transfer_call` message of the exchange. Only the first action of the route
+/// sets `amount_in`, the following ones swap everything received from the previous hop.
+fn swap_msg(
+    route: &[SwapHop],
+    token_in: &AccountId,
+    amount_in: Balance,
+    min_amount_out: Balance,
+) -> serde_json::Value {
+    let mut token_in = token_in;
+    let actions: Vec<serde_json::Value> = route
+        .iter()
+        .enumerate()
+        .map(|(i, hop)| {
+            let hop_min_amount_out = if i + 1 == route.len() {
+                min_amount_out
+            } else {
+                0
+            };
+            let mut action = json!({
+                "pool_id": hop.pool_id,
+                "token_in": token_in,
+                "token_out": hop.token_out,
+                "min_amount_out": hop_min_amount_out.to_string(),
+            });
+            if i == 0 {
+                action["amount_in"] = json!(amount_in.to_string());
+            }
+            token_in = &hop.token_out;
+            action
+        })
+        .collect();
+    json!({ "actions": actions })
+}
+
+/// Returns the token received at the end of the route of a swap transaction.
+fn swap_msg_token_out(actions: &[Action]) -> anyhow::Result<AccountId> {
+    let Some(Action::FunctionCall(call)) = actions.last() else {
+        anyhow::bail!("swap has no ft_transfer_call");
+    };
+    let args: serde_json::Value = serde_json::from_slice(&call.args)?;
+    let msg: serde_json::Value = serde_json::from_str(args["msg"].as_str().unwrap_or_default())?;
+    let token_out = msg["actions"]
+        .as_array()
+        .and_then(|actions| actions.last())
+        .and_then(|action| action["token_out"].as_str())
+        .ok_or_else(|| anyhow::anyhow!("swap message has no actions"))?;
+    Ok(token_out.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::InMemorySigner;
+
+    use crate::transaction::test_utils::create_test_run_opts;
+
+    use super::*;
+
+    #[test]
+    fn test_with_slippage() {
+        assert_eq!(with_slippage(1_000_000, 0.01), 990_000);
+        assert_eq!(with_slippage(1_000_000, 0.0), 1_000_000);
+        assert_eq!(with_slippage(5, 0.5), 3);
+        assert_eq!(with_slippage(1_000_000, 1.0), 1);
+    }
+
+    #[test]
+    fn test_reverse_route() {
+        let route = vec![
+            SwapHop {
+                pool_id: 1,
+                token_out: "usdc.near".parse().unwrap(),
+            },
+            SwapHop {
+                pool_id: 2,
+                token_out: "usdt.near".parse().unwrap(),
+            },
+        ];
+        assert_eq!(
+            reverse_route(&"wrap.near".parse().unwrap(), &route),
+            vec![
+                SwapHop {
+                    pool_id: 2,
+                    token_out: "usdc.near".parse().unwrap(),
+                },
+                SwapHop {
+                    pool_id: 1,
+                    token_out: "wrap.near".parse().unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_swap_msg() {
+        let route = vec![
+            SwapHop {
+                pool_id: 1,
+                token_out: "usdc.near".parse().unwrap(),
+            },
+            SwapHop {
+                pool_id: 2,
+                token_out: "usdt.near".parse().unwrap(),
+            },
+        ];
+        let msg = swap_msg(&route, &"wrap.near".parse().unwrap(), 1000, 900).to_string();
+        let expected = json!({"actions": [
+            {"pool_id": 1, "token_in": "wrap.near", "token_out": "usdc.near", "amount_in": "1000", "min_amount_out": "0"},
+            {"pool_id": 2, "token_in": "usdc.near", "token_out": "usdt.near", "min_amount_out": "900"},
+        ]});
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&msg).unwrap(),
+            expected
+        );
+
+        let actions = vec![Action::FunctionCall(Box::new(FunctionCallAction {
+            method_name: "ft_transfer_call".to_string(),
+            args: json!({ "msg": msg }).to_string().into_bytes(),
+            gas: 0,
+            deposit: 1,
+        }))];
+        assert_eq!(
+            swap_msg_token_out(&actions).unwrap(),
+            "usdt.near".parse::<AccountId>().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_result() {
+        let opts = create_test_run_opts();
+        let signer = InMemorySigner::from_secret_key(opts.signer_id.clone(), opts.signer_key());
+        let rpc_client = JsonRpcClient::connect(&opts.rpc_url);
+        let variant = &Swap {}.variants(&opts)[0];
+        let request = Swap {}
+            .get_transaction_request(&signer, opts, variant, 1, CryptoHash::new())
+            .await
+            .unwrap();
+        let verify = |value: &'static str| {
+            Swap {}.verify_result(&rpc_client, &request, variant, value.as_bytes())
+        };
+        verify("\"1000\"").await.unwrap();
+        let err = verify("\"0\"").await.unwrap_err();
+        assert!(err.is::<InvalidResultError>());
+        assert!(err.to_string().contains("frog.near to bear.near"));
+        assert!(verify("null").await.unwrap_err().is::<InvalidResultError>());
     }
 }
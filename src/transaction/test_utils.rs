@@ -0,0 +1,246 @@
+//! Helpers to build options, requests and outcomes in unit tests.
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use axum::{routing::post, Json, Router};
+use near_crypto::{InMemorySigner, KeyType, SecretKey, Signature};
+use near_jsonrpc_client::methods::tx::RpcTransactionResponse;
+use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, Transaction, TransactionV0};
+use near_primitives::types::BlockHeight;
+use near_primitives::views::{
+    BlockHeaderView, BlockView, ExecutionOutcomeView, ExecutionOutcomeWithIdView,
+    ExecutionStatusView, FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum,
+    FinalExecutionStatus, TxExecutionStatus,
+};
+use serde_json::json;
+use tokio::net::TcpListener;
+
+use crate::config::{BatchAction, Mode, Opts, Secret, SwapDirection};
+use crate::slo::WebhookFormat;
+
+pub(crate) fn create_test_request(actions: Vec<Action>) -> RpcSendTransactionRequest {
+    let signer = InMemorySigner::from_secret_key(
+        "cat.near".parse().unwrap(),
+        SecretKey::from_random(KeyType::ED25519),
+    );
+    let transaction = Transaction::V0(TransactionV0 {
+        signer_id: signer.account_id.clone(),
+        public_key: signer.public_key.clone(),
+        nonce: 1,
+        receiver_id: "frog.near".parse().unwrap(),
+        block_hash: CryptoHash::new(),
+        actions,
+    });
+    RpcSendTransactionRequest {
+        signed_transaction: transaction.sign(&signer.into()),
+        wait_until: TxExecutionStatus::Final,
+    }
+}
+
+/// Returns the outcome of a transaction or receipt executed by `frog.near`.
+pub(crate) fn create_test_outcome(
+    id: CryptoHash,
+    block_hash: CryptoHash,
+    gas_burnt: u64,
+    status: ExecutionStatusView,
+) -> ExecutionOutcomeWithIdView {
+    ExecutionOutcomeWithIdView {
+        proof: vec![],
+        block_hash,
+        id,
+        outcome: ExecutionOutcomeView {
+            logs: vec![],
+            receipt_ids: vec![],
+            gas_burnt,
+            tokens_burnt: gas_burnt as u128 * 100_000_000,
+            executor_id: "frog.near".parse().unwrap(),
+            status,
+            metadata: Default::default(),
+        },
+    }
+}
+
+pub(crate) fn create_test_final_outcome(
+    transaction_outcome: ExecutionOutcomeWithIdView,
+    receipts_outcome: Vec<ExecutionOutcomeWithIdView>,
+) -> FinalExecutionOutcomeView {
+    FinalExecutionOutcomeView {
+        status: FinalExecutionStatus::SuccessValue(vec![]),
+        transaction: create_test_request(vec![]).signed_transaction.into(),
+        transaction_outcome,
+        receipts_outcome,
+    }
+}
+
+/// Returns options of a run on localnet, with all transactions using their defaults. Nothing
+/// listens on the discard port of the RPC URL, requests fail unless a test starts a stand-in.
+pub(crate) fn create_test_run_opts() -> Opts {
+    Opts {
+        mode: Mode::Run,
+        rpc_url: "http://127.0.0.1:9".to_string(),
+        network: Some("localnet".to_string()),
+        rpc_timeout: Duration::from_secs(60),
+        rpc_connect_timeout: Duration::from_secs(10),
+        rpc_headers: None,
+        rpc_api_key: None,
+        rpc_proxy: None,
+        signer_id: "cat.near".parse().unwrap(),
+        signer_key: Some(Secret(SecretKey::from_random(KeyType::ED25519))),
+        signer_key_file: None,
+        remote_signer_url: None,
+        remote_signer_public_key: None,
+        remote_signer_headers: None,
+        receiver_id: "dog.near".parse().unwrap(),
+        receiver_key: None,
+        receiver_key_file: None,
+        relayer_id: None,
+        relayer_key: None,
+        relayer_key_file: None,
+        near_credentials_dir: None,
+        mpc_contract_id: "frog.near".parse().unwrap(),
+        wrap_near_id: "frog.near".parse().unwrap(),
+        ft_account_id: "bear.near".parse().unwrap(),
+        exchange_id: "flamingo.near".parse().unwrap(),
+        nft_contract_id: None,
+        nft_burn_method: "nft_burn".to_string(),
+        mpc_ecdsa_domains: vec![0],
+        mpc_eddsa_domains: vec![1],
+        mpc_ckd_domains: vec![2],
+        mpc_paths: vec![String::new()],
+        pool_id: 0,
+        swap_route: vec![],
+        swap_amount: 1_000_000_000_000_000_000_000,
+        swap_slippage: 0.01,
+        swap_direction: SwapDirection::Forward,
+        deploy_padding: vec![0],
+        call_contract_ids: vec![],
+        call_depths: vec![1],
+        call_fan_outs: vec![],
+        batch_sizes: vec![1],
+        batch_action: BatchAction::Transfer,
+        transaction_kind: vec![],
+        period: Duration::from_millis(1),
+        interval_overwrite: None,
+        function_call_overwrite: None,
+        gas_auto_margin: 0.3,
+        retry_max_attempts: 1,
+        retry_initial_backoff: Duration::from_millis(1),
+        retry_max_backoff: Duration::from_millis(1),
+        retry_jitter: 0.0,
+        retryable_errors: vec![],
+        block_poll_interval: Duration::from_secs(10),
+        block_max_age: Duration::from_secs(60),
+        metric_server_address: SocketAddr::from_str("0.0.0.0:9000").unwrap(),
+        location: "eu".to_string(),
+        repeats_number: 1,
+        kind_parallelism: 1,
+        push_protocol: None,
+        push_address: None,
+        push_interval: Duration::from_secs(15),
+        slo: None,
+        slo_window: Duration::from_secs(3600),
+        slo_min_samples: 1,
+        slo_webhook_url: None,
+        slo_webhook_format: WebhookFormat::Slack,
+        slo_webhook_routing_key: None,
+        otlp_endpoint: None,
+    }
+}
+
+/// Returns a block at `height` with `hash`, all its other fields empty.
+pub(crate) fn create_test_block(height: BlockHeight, hash: CryptoHash) -> BlockView {
+    BlockView {
+        author: "frog.near".parse().unwrap(),
+        header: BlockHeaderView {
+            height,
+            prev_height: None,
+            epoch_id: CryptoHash::new(),
+            next_epoch_id: CryptoHash::new(),
+            hash,
+            prev_hash: CryptoHash::new(),
+            prev_state_root: CryptoHash::new(),
+            block_body_hash: None,
+            chunk_receipts_root: CryptoHash::new(),
+            chunk_headers_root: CryptoHash::new(),
+            chunk_tx_root: CryptoHash::new(),
+            outcome_root: CryptoHash::new(),
+            chunks_included: 0,
+            challenges_root: CryptoHash::new(),
+            timestamp: 0,
+            timestamp_nanosec: 0,
+            random_value: CryptoHash::new(),
+            validator_proposals: vec![],
+            chunk_mask: vec![],
+            gas_price: 0,
+            block_ordinal: None,
+            rent_paid: 0,
+            validator_reward: 0,
+            total_supply: 0,
+            challenges_result: vec![],
+            last_final_block: CryptoHash::new(),
+            last_ds_final_block: CryptoHash::new(),
+            next_bp_hash: CryptoHash::new(),
+            block_merkle_root: CryptoHash::new(),
+            epoch_sync_data_hash: None,
+            approvals: vec![],
+            signature: Signature::empty(KeyType::ED25519),
+            latest_protocol_version: 0,
+        },
+        chunks: vec![],
+    }
+}
+
+/// Returns the result of `send_tx` for `request`, a transaction that succeeded returning `value`.
+pub(crate) fn create_test_tx_result(
+    request: &RpcSendTransactionRequest,
+    value: Vec<u8>,
+) -> serde_json::Value {
+    let outcome = FinalExecutionOutcomeView {
+        status: FinalExecutionStatus::SuccessValue(value.clone()),
+        transaction: request.signed_transaction.clone().into(),
+        transaction_outcome: create_test_outcome(
+            request.signed_transaction.get_hash(),
+            CryptoHash::new(),
+            1,
+            ExecutionStatusView::SuccessValue(value),
+        ),
+        receipts_outcome: vec![],
+    };
+    serde_json::to_value(RpcTransactionResponse {
+        final_execution_outcome: Some(FinalExecutionOutcomeViewEnum::FinalExecutionOutcome(
+            outcome,
+        )),
+        final_execution_status: TxExecutionStatus::Final,
+    })
+    .unwrap()
+}
+
+/// Starts a JSON-RPC server answering every request with the result, or the error, returned by
+/// `handler` from its method and params, returning its URL.
+pub(crate) async fn start_test_rpc<F>(handler: F) -> String
+where
+    F: Fn(&str, &serde_json::Value) -> Result<serde_json::Value, serde_json::Value>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    let app = Router::new().route(
+        "/",
+        post(move |Json(body): Json<serde_json::Value>| async move {
+            let method = body["method"].as_str().unwrap_or_default();
+            Json(match handler(method, &body["params"]) {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": body["id"], "result": result }),
+                Err(error) => json!({ "jsonrpc": "2.0", "id": body["id"], "error": error }),
+            })
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    url
+}
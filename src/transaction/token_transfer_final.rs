@@ -1,7 +1,6 @@
 use crate::config::Opts;
 use crate::TransactionSample;
 use async_trait::async_trait;
-use near_crypto::InMemorySigner;
 use near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest;
 use near_primitives::action::TransferAction;
 use near_primitives::hash::CryptoHash;
@@ -9,7 +8,8 @@ use near_primitives::transaction::{Action, Transaction, TransactionV0};
 use near_primitives::types::Nonce;
 use near_primitives::views::TxExecutionStatus;
 
-use super::TransactionKind;
+use super::signer::{sign_transaction, Signer};
+use super::{TransactionKind, Variant};
 
 pub struct TokenTransferFinal {}
 
@@ -23,24 +23,25 @@ impl TransactionSample for TokenTransferFinal {
         "NEAR transfer, wait_until Final"
     }
 
-    fn get_transaction_request(
+    async fn get_transaction_request(
         &self,
-        signer: InMemorySigner,
+        signer: &dyn Signer,
         opts: Opts,
+        _variant: &Variant,
         nonce: Nonce,
         block_hash: CryptoHash,
-    ) -> RpcSendTransactionRequest {
+    ) -> anyhow::Result<RpcSendTransactionRequest> {
         let transaction = Transaction::V0(TransactionV0 {
-            signer_id: signer.account_id.clone(),
-            public_key: signer.public_key.clone(),
+            signer_id: signer.account_id().clone(),
+            public_key: signer.public_key(),
             nonce: nonce + 1,
             receiver_id: opts.receiver_id,
             block_hash,
             actions: vec![Action::Transfer(TransferAction { deposit: 1 })],
         });
-        RpcSendTransactionRequest {
-            signed_transaction: transaction.sign(&signer.into()),
+        Ok(RpcSendTransactionRequest {
+            signed_transaction: sign_transaction(signer, transaction).await?,
             wait_until: TxExecutionStatus::Final,
-        }
+        })
     }
 }